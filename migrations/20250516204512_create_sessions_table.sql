@@ -0,0 +1,9 @@
+-- Create Sessions Table
+CREATE TABLE sessions
+(
+    id          TEXT        NOT NULL,
+    data        BYTEA       NOT NULL,
+    expiry_date timestamptz NOT NULL,
+    PRIMARY KEY (id)
+);
+CREATE INDEX sessions_expiry_date_idx ON sessions (expiry_date);
@@ -0,0 +1,9 @@
+-- Give every subscriber a token to unsubscribe with
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+    UPDATE subscriptions
+        SET unsubscribe_token = substr(md5(random()::text || id::text), 1, 25)
+        WHERE unsubscribe_token IS NULL;
+    ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key UNIQUE (unsubscribe_token);
+COMMIT;
@@ -0,0 +1,7 @@
+-- Only keep the SHA-256 digest of confirmation tokens.
+-- Links already sent out keep working: incoming tokens are hashed before the lookup.
+BEGIN;
+    ALTER TABLE subscription_tokens RENAME COLUMN subscription_token TO subscription_token_hash;
+    UPDATE subscription_tokens
+        SET subscription_token_hash = encode(sha256(convert_to(subscription_token_hash, 'UTF8')), 'hex');
+COMMIT;
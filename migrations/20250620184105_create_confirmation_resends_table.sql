@@ -0,0 +1,7 @@
+-- Track confirmation emails re-sent on request, to rate limit them per address
+CREATE TABLE confirmation_resends
+(
+    email        TEXT        NOT NULL,
+    requested_at timestamptz NOT NULL
+);
+CREATE INDEX confirmation_resends_email_requested_at_idx ON confirmation_resends (email, requested_at);
@@ -0,0 +1,16 @@
+-- Create Email Outbox Table
+CREATE TABLE email_outbox
+(
+    email_id      uuid        NOT NULL PRIMARY KEY,
+    recipient     TEXT        NOT NULL,
+    subject       TEXT        NOT NULL,
+    html_content  TEXT        NOT NULL,
+    text_content  TEXT        NOT NULL,
+    created_at    timestamptz NOT NULL DEFAULT now(),
+    n_retries     SMALLINT    NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    delivered_at  timestamptz,
+    failed_at     timestamptz
+);
+CREATE INDEX email_outbox_pending_idx ON email_outbox (execute_after)
+    WHERE delivered_at IS NULL AND failed_at IS NULL;
@@ -0,0 +1,3 @@
+-- Language the subscriber reads our emails and pages in.
+-- Everyone who signed up so far got English copy.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
@@ -0,0 +1,6 @@
+-- Sent and abandoned emails must not keep working confirmation links around
+UPDATE email_outbox
+SET subject = '', html_content = '', text_content = ''
+WHERE delivered_at IS NOT NULL OR failed_at IS NOT NULL;
+CREATE INDEX email_outbox_finished_idx ON email_outbox (coalesce(delivered_at, failed_at))
+    WHERE delivered_at IS NOT NULL OR failed_at IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Keys saved before requests were fingerprinted cannot be reused
+ALTER TABLE idempotency ADD COLUMN request_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE idempotency ALTER COLUMN request_hash DROP DEFAULT;
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,10 @@
+-- Resends are requested by the API and processed in the background, so that
+-- answering a request takes the same time whether the address is pending or not
+ALTER TABLE confirmation_resends ADD COLUMN resend_id uuid NOT NULL DEFAULT gen_random_uuid() PRIMARY KEY;
+ALTER TABLE confirmation_resends ALTER COLUMN resend_id DROP DEFAULT;
+ALTER TABLE confirmation_resends ADD COLUMN processed_at timestamptz;
+ALTER TABLE confirmation_resends ADD COLUMN sent BOOLEAN NOT NULL DEFAULT false;
+-- Past requests were processed on the spot: count them against the rate limit
+UPDATE confirmation_resends SET processed_at = requested_at, sent = true;
+CREATE INDEX confirmation_resends_pending_idx ON confirmation_resends (requested_at)
+    WHERE processed_at IS NULL;
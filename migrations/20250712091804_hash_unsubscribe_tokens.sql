@@ -0,0 +1,19 @@
+-- Only keep the SHA-256 digest of unsubscribe tokens.
+-- Links are rendered from the plaintext, so every newsletter email now gets a token of its own.
+-- Links already sent out keep working, except those from the `md5(random())` backfill:
+-- they could be guessed, so those subscribers get new ones with their next issue.
+BEGIN;
+    CREATE TABLE unsubscribe_tokens(
+        unsubscribe_token_hash TEXT NOT NULL,
+        subscriber_id UUID NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        created_at timestamptz NOT NULL DEFAULT now(),
+        PRIMARY KEY (unsubscribe_token_hash)
+    );
+    CREATE INDEX unsubscribe_tokens_subscriber_id_idx ON unsubscribe_tokens (subscriber_id);
+    CREATE INDEX unsubscribe_tokens_created_at_idx ON unsubscribe_tokens (created_at);
+    INSERT INTO unsubscribe_tokens (unsubscribe_token_hash, subscriber_id)
+        SELECT encode(sha256(convert_to(unsubscribe_token, 'UTF8')), 'hex'), id
+        FROM subscriptions
+        WHERE unsubscribe_token !~ '^[0-9a-f]{25}$';
+    ALTER TABLE subscriptions DROP COLUMN unsubscribe_token;
+COMMIT;
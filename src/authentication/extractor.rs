@@ -0,0 +1,79 @@
+use crate::authentication::{AuthError, Credentials, validate_credentials};
+use crate::problem::Problem;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use secrecy::SecretString;
+use tracing::field::display;
+use uuid::Uuid;
+
+/// An admin who authenticated via HTTP Basic auth.
+/// Handlers taking this extractor reject anonymous requests with a 401.
+pub struct BasicAuthUser {
+    pub user_id: Uuid,
+}
+
+impl FromRequestParts<AppState> for BasicAuthUser {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let credentials = basic_authentication(&parts.headers).map_err(AuthError::InvalidCredentials)?;
+        tracing::Span::current().record("username", display(&credentials.username));
+        let user_id = validate_credentials(credentials, &state.db).await?;
+        tracing::Span::current().record("user_id", display(&user_id));
+        Ok(Self { user_id })
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        match &self {
+            AuthError::InvalidCredentials(e) => {
+                tracing::debug!("Authentication failed: {e:?}");
+                // Which of the username or the password is wrong stays in the logs.
+                let mut response = Problem::new(StatusCode::UNAUTHORIZED, self.to_string()).into_response();
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="publish""#),
+                );
+                response
+            }
+            AuthError::UnexpectedError(_) => Problem::unexpected(&self).into_response(),
+        }
+    }
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials =
+        String::from_utf8(decoded_bytes).context("The decoded credential string is not valid UTF8.")?;
+
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: SecretString::from(password),
+    })
+}
@@ -0,0 +1,36 @@
+use crate::problem::Problem;
+use crate::session_state::TypedSession;
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Redirect, Response};
+use tracing::field::display;
+use uuid::Uuid;
+
+/// The id of the admin logged in via session, inserted in the request
+/// extensions by `reject_anonymous_users`.
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(pub Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+pub async fn reject_anonymous_users(session: TypedSession, mut request: Request, next: Next) -> Response {
+    match session.get_user_id().await {
+        Ok(Some(user_id)) => {
+            tracing::Span::current().record("user_id", display(&user_id));
+            request.extensions_mut().insert(UserId(user_id));
+            next.run(request).await
+        }
+        Ok(None) => {
+            tracing::debug!("The user has not logged in");
+            Redirect::to("/login").into_response()
+        }
+        Err(e) => {
+            let e = anyhow::Error::from(e).context("Failed to read the user id from the session");
+            Problem::unexpected(&e).into_response()
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod extractor;
+mod middleware;
+mod password;
+
+pub use extractor::BasicAuthUser;
+pub use middleware::{UserId, reject_anonymous_users};
+pub use password::{AuthError, Credentials, compute_password_hash, create_initial_admin, validate_credentials};
@@ -0,0 +1,120 @@
+use crate::routes::error_chain_fmt;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use rand::RngCore;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use uuid::{ContextV7, Timestamp, Uuid};
+
+#[derive(thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(credentials: Credentials, pool: &PgPool) -> Result<Uuid, AuthError> {
+    // Verify against a fallback hash when the user does not exist, so that
+    // response times do not reveal which usernames are registered.
+    let mut user_id = None;
+    let mut expected_password_hash = SecretString::from(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        QNxL2XTX9m05xKoHxvyRDA$\
+        vkLdtmCeQwxnQFc72VD3Y0fUpJVB+tBAA9miS5vurs0",
+    );
+
+    if let Some((stored_user_id, stored_password_hash)) = get_stored_credentials(&credentials.username, pool).await? {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || verify_password_hash(expected_password_hash, credentials.password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+
+    user_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown username."))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(username: &str, pool: &PgPool) -> Result<Option<(Uuid, SecretString)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id, password_hash FROM users WHERE username = $1"#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?
+    .map(|row| (row.user_id, SecretString::from(row.password_hash)));
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Verify password hash", skip(expected_password_hash, password_candidate))]
+fn verify_password_hash(
+    expected_password_hash: SecretString,
+    password_candidate: SecretString,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+
+    Argon2::default()
+        .verify_password(password_candidate.expose_secret().as_bytes(), &expected_password_hash)
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+/// Hash a password with Argon2id, using the parameters we expect in `users.password_hash`.
+pub fn compute_password_hash(password: SecretString) -> Result<SecretString, anyhow::Error> {
+    let mut salt = [0u8; 16];
+    rand::rng().fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|e| anyhow::anyhow!(e))?;
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)
+    .map_err(|e| anyhow::anyhow!(e))?
+    .to_string();
+    Ok(SecretString::from(password_hash))
+}
+
+/// Create the first admin, unless there is already a user.
+/// Returns `false` if nothing was created.
+#[tracing::instrument(name = "Create initial admin", skip(credentials, pool), fields(username = %credentials.username))]
+pub async fn create_initial_admin(credentials: Credentials, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(credentials.password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        SELECT $1, $2, $3
+        WHERE NOT EXISTS (SELECT 1 FROM users)
+        "#,
+        Uuid::new_v7(Timestamp::now(ContextV7::new())),
+        credentials.username,
+        password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the initial admin.")?;
+    Ok(result.rows_affected() > 0)
+}
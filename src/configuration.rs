@@ -1,30 +1,286 @@
-use crate::domain::EmailAddress;
+use crate::domain::{EmailAddress, EmailAddressError};
+use crate::email_client::{
+    CircuitBreaker, EmailClient, EmailProvider, EmailTransport, FailoverEmailClient, FileEmailClient, RateLimiter,
+    RetryPolicy, SmtpEmailClient,
+};
+use lettre::transport::smtp::authentication::Credentials;
+use reqwest::Url;
 use secrecy::{ExposeSecret, SecretString};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::sync::Arc;
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub subscription_tokens: SubscriptionTokenSettings,
+    pub templates: TemplateSettings,
+    #[serde(default)]
+    pub initial_admin: Option<InitialAdminSettings>,
 }
 
+/// Created on startup while there are no users, so that the first admin's
+/// password is a deployment secret (e.g. `APP_INITIAL_ADMIN__PASSWORD`).
+#[derive(serde::Deserialize, Clone)]
+pub struct InitialAdminSettings {
+    pub username: String,
+    pub password: SecretString,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TemplateSettings {
+    /// Relative paths are resolved from the working directory.
+    pub directory: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionTokenSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_minutes: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub sweep_interval_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_resends_per_hour: u32,
+}
+
+impl SubscriptionTokenSettings {
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_minutes * 60)
+    }
+
+    pub fn sweep_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.sweep_interval_seconds)
+    }
+}
+
+/// The top-level fields describe the primary provider, `fallbacks` are tried
+/// in order whenever it is unavailable.
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    pub kind: EmailTransportKind,
+    /// Identifies the primary provider in traces, defaults to `kind`.
+    pub provider_name: Option<String>,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    /// Largest number of emails sent in a single request to HTTP providers.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: usize,
+    pub retry: RetrySettings,
+    pub rate_limit: RateLimitSettings,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub smtp: SmtpSettings,
+    pub file_sink_directory: String,
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProviderSettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FallbackProviderSettings {
+    Http {
+        name: String,
+        base_url: String,
+        authorization_token: SecretString,
+        /// The provider's own quota. Defaults to the primary provider's settings,
+        /// in a bucket of its own.
+        #[serde(default)]
+        rate_limit: Option<RateLimitSettings>,
+    },
+    Smtp {
+        name: String,
+        smtp: SmtpSettings,
+    },
+}
+
+/// Outgoing requests allowed by the provider's quota. Each HTTP provider gets its own bucket.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub requests_per_second: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+}
+
+impl RateLimitSettings {
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.requests_per_second, self.burst)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_threshold: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cooldown_seconds: u64,
+}
+
+impl CircuitBreakerSettings {
+    pub fn circuit_breaker(&self) -> CircuitBreaker {
+        CircuitBreaker::new(
+            self.failure_threshold,
+            std::time::Duration::from_secs(self.cooldown_seconds),
+        )
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RetrySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_milliseconds: u64,
+    pub jitter: bool,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            base_delay: std::time::Duration::from_millis(self.base_delay_milliseconds),
+            max_delay: std::time::Duration::from_millis(self.max_delay_milliseconds),
+            jitter: self.jitter,
+        }
+    }
+}
+
+/// Which `EmailTransport` implementation delivers our emails.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    Http,
+    Smtp,
+    File,
+}
+
+impl EmailTransportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTransportKind::Http => "http",
+            EmailTransportKind::Smtp => "smtp",
+            EmailTransportKind::File => "file",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    pub require_tls: bool,
+}
+
+impl SmtpSettings {
+    pub fn client(&self, sender: EmailAddress, timeout: std::time::Duration) -> SmtpEmailClient {
+        let transport =
+            SmtpEmailClient::transport(&self.host, self.port, self.require_tls, self.credentials(), timeout)
+                .expect("Failed to configure the SMTP transport");
+        SmtpEmailClient::new(transport, sender)
+    }
+
+    pub fn credentials(&self) -> Option<Credentials> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => {
+                Some(Credentials::new(username.clone(), password.expose_secret().to_owned()))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<EmailAddress, String> {
+    pub fn sender(&self) -> Result<EmailAddress, EmailAddressError> {
         EmailAddress::parse(self.sender_email.clone())
     }
 
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn client(self) -> Arc<dyn EmailTransport> {
+        let sender_email = self.sender().expect("Invalid sender email address");
+        let timeout = self.timeout();
+        let transport: Box<dyn EmailTransport> = match self.kind {
+            EmailTransportKind::Http => Box::new(http_client(
+                &self.base_url,
+                sender_email.clone(),
+                self.authorization_token.clone(),
+                timeout,
+                self.retry.policy(),
+                self.rate_limit.rate_limiter(),
+                self.batch_size,
+            )),
+            EmailTransportKind::Smtp => Box::new(self.smtp.client(sender_email.clone(), timeout)),
+            EmailTransportKind::File => Box::new(
+                FileEmailClient::new(&self.file_sink_directory, sender_email.clone())
+                    .expect("Failed to create the email sink directory"),
+            ),
+        };
+        let mut providers = vec![EmailProvider {
+            name: self.provider_name.clone().unwrap_or_else(|| self.kind.as_str().into()),
+            transport,
+            circuit_breaker: self.circuit_breaker.circuit_breaker(),
+        }];
+        for fallback in self.fallbacks {
+            let (name, transport): (_, Box<dyn EmailTransport>) = match fallback {
+                FallbackProviderSettings::Http {
+                    name,
+                    base_url,
+                    authorization_token,
+                    rate_limit,
+                } => (
+                    name,
+                    Box::new(http_client(
+                        &base_url,
+                        sender_email.clone(),
+                        authorization_token,
+                        timeout,
+                        self.retry.policy(),
+                        rate_limit.as_ref().unwrap_or(&self.rate_limit).rate_limiter(),
+                        self.batch_size,
+                    )),
+                ),
+                FallbackProviderSettings::Smtp { name, smtp } => {
+                    (name, Box::new(smtp.client(sender_email.clone(), timeout)))
+                }
+            };
+            providers.push(EmailProvider {
+                name,
+                transport,
+                circuit_breaker: self.circuit_breaker.circuit_breaker(),
+            });
+        }
+        Arc::new(FailoverEmailClient::new(providers))
+    }
+}
+
+fn http_client(
+    base_url: &str,
+    sender: EmailAddress,
+    authorization_token: SecretString,
+    timeout: std::time::Duration,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    batch_size: usize,
+) -> EmailClient {
+    let base_url = Url::parse(base_url).expect("Failed to parse URL");
+    EmailClient::new(
+        base_url,
+        sender,
+        authorization_token,
+        timeout,
+        retry_policy,
+        rate_limiter,
+    )
+    .with_batch_size(batch_size)
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -33,6 +289,25 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    /// Where browsers go after following a confirmation link, with a `status`
+    /// query parameter. Confirmation pages are served by the API if unset.
+    #[serde(default)]
+    pub confirmation_redirect_url: Option<String>,
+    /// Serves an interactive reference of the API at `/docs`. The document
+    /// itself is always available at `/openapi.json`.
+    #[serde(default)]
+    pub api_docs_ui: bool,
+    /// Serves `/metrics` on this port instead of the public one.
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+    /// Where the metrics port listens, localhost unless only the scraper can
+    /// reach another interface: `host` is usually public.
+    #[serde(default = "default_metrics_host")]
+    pub metrics_host: String,
+}
+
+fn default_metrics_host() -> String {
+    "127.0.0.1".into()
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -0,0 +1,199 @@
+use crate::configuration::{Settings, SubscriptionTokenSettings};
+use crate::domain::{EmailAddress, Locale, SubscriptionToken};
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::routes::{delete_tokens, enqueue_confirmation_email, store_token};
+use crate::startup::Application;
+use crate::templates::Templates;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tracing::{Span, field::display};
+use uuid::Uuid;
+
+pub async fn run_resender_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = Application::get_connection_pool(&configuration.database);
+    let templates = Templates::load(&configuration.templates.directory)?;
+    resender_loop(
+        connection_pool,
+        templates,
+        configuration.application.base_url,
+        configuration.subscription_tokens,
+    )
+    .await
+}
+
+async fn resender_loop(
+    pool: PgPool,
+    templates: Templates,
+    base_url: String,
+    settings: SubscriptionTokenSettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_resend_confirmation(&pool, &templates, &base_url, &settings).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Handle the oldest resend request: send a fresh confirmation link if the
+/// address belongs to a pending subscriber and is not over its hourly quota.
+#[tracing::instrument(skip_all, fields(resend_id = tracing::field::Empty), err)]
+pub async fn try_resend_confirmation(
+    pool: &PgPool,
+    templates: &Templates,
+    base_url: &str,
+    settings: &SubscriptionTokenSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(request) = dequeue_request(&mut transaction).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("resend_id", display(request.resend_id));
+
+    let sent = match EmailAddress::parse(request.email) {
+        Ok(email) => resend(&mut transaction, templates, base_url, settings, &email).await?,
+        Err(e) => {
+            tracing::error!(error.message = %e, "Skipping a resend request for an invalid address");
+            false
+        }
+    };
+    mark_as_processed(&mut transaction, request.resend_id, sent).await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Returns whether a new confirmation email was enqueued.
+async fn resend(
+    transaction: &mut Transaction<'static, Postgres>,
+    templates: &Templates,
+    base_url: &str,
+    settings: &SubscriptionTokenSettings,
+    email: &EmailAddress,
+) -> Result<bool, anyhow::Error> {
+    let Some(subscriber) = get_pending_subscriber(transaction, email)
+        .await
+        .context("Failed to look up the pending subscriber")?
+    else {
+        return Ok(false);
+    };
+
+    let n_recent_resends = count_recent_resends(transaction, email)
+        .await
+        .context("Failed to count the confirmation emails recently re-sent")?;
+    if n_recent_resends >= i64::from(settings.max_resends_per_hour) {
+        tracing::info!("Too many confirmation emails re-sent in the last hour, skipping");
+        return Ok(false);
+    }
+
+    delete_tokens(transaction, subscriber.id)
+        .await
+        .context("Failed to invalidate the previous confirmation tokens")?;
+
+    let subscription_token = SubscriptionToken::new();
+    let expires_at = Utc::now()
+        + chrono::Duration::from_std(settings.ttl()).context("The confirmation token TTL is out of range")?;
+    store_token(transaction, subscriber.id, &subscription_token, expires_at)
+        .await
+        .context("Failed to store the confirmation token for a pending subscriber")?;
+
+    enqueue_confirmation_email(
+        transaction,
+        templates,
+        email,
+        &subscriber.name,
+        Locale::parse(&subscriber.locale).unwrap_or_default(),
+        base_url.to_owned(),
+        subscription_token.as_ref(),
+    )
+    .await
+    .context("Failed to enqueue the confirmation email")?;
+    Ok(true)
+}
+
+struct ResendRequest {
+    resend_id: Uuid,
+    email: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_request(
+    transaction: &mut Transaction<'static, Postgres>,
+) -> Result<Option<ResendRequest>, anyhow::Error> {
+    let request = sqlx::query_as!(
+        ResendRequest,
+        r#"
+        SELECT resend_id, email
+        FROM confirmation_resends
+        WHERE processed_at IS NULL
+        ORDER BY requested_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(request)
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn mark_as_processed(
+    transaction: &mut Transaction<'static, Postgres>,
+    resend_id: Uuid,
+    sent: bool,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE confirmation_resends SET processed_at = now(), sent = $2 WHERE resend_id = $1"#,
+        resend_id,
+        sent,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+struct PendingSubscriber {
+    id: Uuid,
+    name: String,
+    locale: String,
+}
+
+/// Locks the subscriber row, so that concurrent resends for the same address
+/// are counted one after the other.
+#[tracing::instrument(name = "Get pending subscriber", skip(transaction, email))]
+async fn get_pending_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &EmailAddress,
+) -> Result<Option<PendingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingSubscriber,
+        r#"
+        SELECT id, name, locale FROM subscriptions
+        WHERE email = $1 AND status = 'pending_confirmation'
+        FOR UPDATE
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Count recent confirmation resends", skip(transaction, email))]
+async fn count_recent_resends(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &EmailAddress,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!" FROM confirmation_resends
+        WHERE email = $1 AND sent AND requested_at > now() - interval '1 hour'
+        "#,
+        email.as_ref()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(row.count)
+}
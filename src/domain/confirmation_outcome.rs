@@ -0,0 +1,32 @@
+/// What happened when a subscriber followed their confirmation link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationOutcome {
+    /// Includes links followed again after confirming.
+    Confirmed,
+    Expired,
+    /// The token is malformed or unknown.
+    Invalid,
+}
+
+impl ConfirmationOutcome {
+    pub const ALL: [ConfirmationOutcome; 3] = [
+        ConfirmationOutcome::Confirmed,
+        ConfirmationOutcome::Expired,
+        ConfirmationOutcome::Invalid,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfirmationOutcome::Confirmed => "confirmed",
+            ConfirmationOutcome::Expired => "expired",
+            ConfirmationOutcome::Invalid => "invalid",
+        }
+    }
+}
+
+impl AsRef<str> for ConfirmationOutcome {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
@@ -0,0 +1,91 @@
+/// The languages we have translations for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Es];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+        }
+    }
+
+    /// Language tags are matched on their primary subtag, e.g. `es-MX` is Spanish.
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let language = tag.trim().split(['-', '_']).next()?;
+        Locale::ALL
+            .into_iter()
+            .find(|l| l.as_str().eq_ignore_ascii_case(language))
+    }
+
+    /// The supported language the client prefers, as listed in an `Accept-Language` header.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // Stable, so that ties keep the order of the header.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges.into_iter().find_map(|(tag, _)| Locale::parse(tag))
+    }
+}
+
+impl AsRef<str> for Locale {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn regional_variants_match_their_language() {
+        assert_some_eq!(Locale::parse("es-MX"), Locale::Es);
+        assert_some_eq!(Locale::parse("EN_gb"), Locale::En);
+    }
+
+    #[test]
+    fn unsupported_languages_are_rejected() {
+        assert_none!(Locale::parse("fr"));
+        assert_none!(Locale::parse(""));
+    }
+
+    #[test]
+    fn the_preferred_supported_language_is_picked() {
+        assert_some_eq!(Locale::from_accept_language("fr-FR, es;q=0.8, en;q=0.5"), Locale::Es);
+        assert_some_eq!(Locale::from_accept_language("en;q=0.2, es-AR;q=0.9"), Locale::Es);
+    }
+
+    #[test]
+    fn ties_keep_the_order_of_the_header() {
+        assert_some_eq!(Locale::from_accept_language("es, en"), Locale::Es);
+    }
+
+    #[test]
+    fn languages_with_a_zero_quality_are_not_acceptable() {
+        assert_none!(Locale::from_accept_language("es;q=0, fr"));
+    }
+
+    #[test]
+    fn no_supported_language_yields_none() {
+        assert_none!(Locale::from_accept_language("de, *;q=0.1"));
+        assert_none!(Locale::from_accept_language(""));
+    }
+}
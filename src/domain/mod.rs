@@ -1,9 +1,13 @@
+mod confirmation_outcome;
+mod locale;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
 mod subscription_token;
 
-pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::EmailAddress;
-pub use subscriber_name::SubscriberName;
+pub use confirmation_outcome::ConfirmationOutcome;
+pub use locale::Locale;
+pub use new_subscriber::{NewSubscriber, NewSubscriberError};
+pub use subscriber_email::{EmailAddress, EmailAddressError};
+pub use subscriber_name::{SubscriberName, SubscriberNameError};
 pub use subscription_token::{SubscriptionToken, TokenError};
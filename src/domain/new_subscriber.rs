@@ -1,8 +1,79 @@
-use crate::domain::EmailAddress;
-use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::{EmailAddress, EmailAddressError, SubscriberName, SubscriberNameError};
 
 #[derive(Debug)]
 pub struct NewSubscriber {
     pub email: EmailAddress,
     pub name: SubscriberName,
 }
+
+/// A field of a new subscriber and the rule it broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum NewSubscriberError {
+    #[error(transparent)]
+    Name(#[from] SubscriberNameError),
+
+    #[error(transparent)]
+    Email(#[from] EmailAddressError),
+}
+
+impl NewSubscriberError {
+    pub fn field(&self) -> &'static str {
+        match self {
+            NewSubscriberError::Name(_) => "name",
+            NewSubscriberError::Email(_) => "email",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            NewSubscriberError::Name(e) => e.code(),
+            NewSubscriberError::Email(e) => e.code(),
+        }
+    }
+}
+
+impl NewSubscriber {
+    /// Checks every field, so that all of their errors can be reported at once.
+    pub fn parse(name: String, email: String) -> Result<NewSubscriber, Vec<NewSubscriberError>> {
+        match (SubscriberName::parse(name), EmailAddress::parse(email)) {
+            (Ok(name), Ok(email)) => Ok(Self { email, name }),
+            (name, email) => Err(name
+                .err()
+                .map(NewSubscriberError::from)
+                .into_iter()
+                .chain(email.err().map(NewSubscriberError::from))
+                .collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewSubscriber, NewSubscriberError};
+    use crate::domain::{EmailAddressError, SubscriberNameError};
+    use claims::{assert_err_eq, assert_ok};
+
+    #[test]
+    fn a_valid_subscriber_is_parsed_successfully() {
+        assert_ok!(NewSubscriber::parse("Ursula".into(), "ursula_le_guin@gmail.com".into()));
+    }
+
+    #[test]
+    fn errors_from_every_field_are_reported() {
+        assert_err_eq!(
+            NewSubscriber::parse("".into(), "definitely-not-an-email".into()),
+            vec![
+                NewSubscriberError::Name(SubscriberNameError::Empty),
+                NewSubscriberError::Email(EmailAddressError::Invalid),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_know_their_field_and_reason() {
+        let error = NewSubscriberError::Name(SubscriberNameError::TooLong);
+
+        assert_eq!(error.field(), "name");
+        assert_eq!(error.code(), "too_long");
+    }
+}
@@ -1,14 +1,36 @@
 use validator::ValidateEmail;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EmailAddress(String);
 
+/// The rule an email address broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum EmailAddressError {
+    #[error("The email address must not be empty.")]
+    Empty,
+
+    #[error("The email address is not valid.")]
+    Invalid,
+}
+
+impl EmailAddressError {
+    /// Stable across releases, unlike the messages, so clients can match on it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EmailAddressError::Empty => "empty",
+            EmailAddressError::Invalid => "invalid",
+        }
+    }
+}
+
 impl EmailAddress {
-    pub fn parse(s: String) -> Result<EmailAddress, String> {
-        if s.validate_email() {
+    pub fn parse(s: String) -> Result<EmailAddress, EmailAddressError> {
+        if s.trim().is_empty() {
+            Err(EmailAddressError::Empty)
+        } else if s.validate_email() {
             Ok(Self(s))
         } else {
-            Err(format!("{s} is not a valid email."))
+            Err(EmailAddressError::Invalid)
         }
     }
 }
@@ -21,8 +43,8 @@ impl AsRef<str> for EmailAddress {
 
 #[cfg(test)]
 mod tests {
-    use super::EmailAddress;
-    use claims::assert_err;
+    use super::{EmailAddress, EmailAddressError};
+    use claims::{assert_err, assert_err_eq};
     use fake::Fake;
     use fake::faker::internet::en::SafeEmail;
     use quickcheck::Gen;
@@ -37,6 +59,12 @@ mod tests {
         }
     }
 
+    #[test]
+    fn empty_emails_are_told_apart_from_invalid_ones() {
+        assert_err_eq!(EmailAddress::parse(" ".into()), EmailAddressError::Empty);
+        assert_err_eq!(EmailAddress::parse("namemail.com".into()), EmailAddressError::Invalid);
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
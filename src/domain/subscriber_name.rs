@@ -1,19 +1,43 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+const MAX_LENGTH: usize = 256;
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
-impl SubscriberName {
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
+/// The rule a subscriber name broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum SubscriberNameError {
+    #[error("The name must not be empty.")]
+    Empty,
 
-        let is_too_long = s.graphemes(true).count() > 256;
+    #[error("The name must not be longer than 256 characters.")]
+    TooLong,
+
+    #[error("The name must not contain any of / ( ) \" < > \\ {{ }}.")]
+    ForbiddenCharacters,
+}
 
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
+impl SubscriberNameError {
+    /// Stable across releases, unlike the messages, so clients can match on it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubscriberNameError::Empty => "empty",
+            SubscriberNameError::TooLong => "too_long",
+            SubscriberNameError::ForbiddenCharacters => "forbidden_characters",
+        }
+    }
+}
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{s} is not a valid subscriber name."))
+impl SubscriberName {
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
+        if s.trim().is_empty() {
+            Err(SubscriberNameError::Empty)
+        } else if s.graphemes(true).count() > MAX_LENGTH {
+            Err(SubscriberNameError::TooLong)
+        } else if s.chars().any(|c| FORBIDDEN_CHARACTERS.contains(&c)) {
+            Err(SubscriberNameError::ForbiddenCharacters)
         } else {
             Ok(Self(s))
         }
@@ -28,8 +52,8 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberName;
-    use claims::{assert_err, assert_ok};
+    use super::{SubscriberName, SubscriberNameError};
+    use claims::{assert_err_eq, assert_ok};
 
     #[test]
     fn a_256_grapheme_long_name_is_valid() {
@@ -40,26 +64,26 @@ mod tests {
     #[test]
     fn name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::TooLong);
     }
 
     #[test]
     fn whitespace_only_names_are_rejected() {
         let name = " ".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::Empty);
     }
 
     #[test]
     fn empty_string_is_rejected() {
         let name = "".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::Empty);
     }
 
     #[test]
     fn names_containing_an_invalid_character_are_rejected() {
         for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
             let name = name.to_string();
-            assert_err!(SubscriberName::parse(name));
+            assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::ForbiddenCharacters);
         }
     }
 
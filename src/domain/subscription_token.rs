@@ -1,6 +1,7 @@
 use rand::distr::{Alphanumeric, SampleString};
 use rand::prelude::ThreadRng;
 use rand::rng;
+use sha2::{Digest, Sha256};
 
 const TOKEN_LENGTH: usize = 25;
 
@@ -40,6 +41,12 @@ impl SubscriptionToken {
         Ok(Self(s.to_string()))
     }
 
+    /// The SHA-256 digest of the token, hex-encoded.
+    /// Only the digest is persisted, so a database leak does not leak valid links.
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+
     fn generate_token_with_rng(rng: &mut ThreadRng) -> Self {
         let token = Alphanumeric.sample_string(rng, TOKEN_LENGTH);
         Self::parse(&token).unwrap()
@@ -83,6 +90,16 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_token_hash_is_a_stable_sha256_hex_digest() {
+        let token = SubscriptionToken::parse("5J91vXYKj2xP8LmN3qRt4wZhA").unwrap();
+        let hash = token.hash();
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(hash, token.hash());
+        assert_ne!(hash, SubscriptionToken::new().hash());
+    }
+
     #[test]
     fn test_invalid_format_token() {
         let invalid_token = "!@#$%^&*()?><:{}[]";
@@ -1,215 +0,0 @@
-use crate::domain::EmailAddress;
-use reqwest::{Client, Url};
-use secrecy::{ExposeSecret, SecretString};
-
-pub struct EmailClient {
-    http_client: Client,
-    base_url: Url,
-    sender: EmailAddress,
-    authorization_token: SecretString,
-}
-
-impl EmailClient {
-    pub fn new(
-        base_url: Url,
-        sender: EmailAddress,
-        authorization_token: SecretString,
-        timeout: std::time::Duration,
-    ) -> Self {
-        let http_client = Client::builder().timeout(timeout).build().unwrap();
-        Self {
-            http_client,
-            base_url,
-            sender,
-            authorization_token,
-        }
-    }
-
-    pub async fn send_email(
-        &self,
-        recipient: EmailAddress,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        // No matter the input
-        let url = self.base_url.join("/emails").expect("Failed to parse URL");
-        let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html: html_content,
-            text: text_content,
-        };
-        self.http_client
-            .post(url)
-            .bearer_auth(self.authorization_token.expose_secret())
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
-    }
-}
-
-#[derive(serde::Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct SendEmailRequest<'a> {
-    from: &'a str,
-    to: &'a str,
-    subject: &'a str,
-    html: &'a str,
-    text: &'a str,
-}
-
-#[cfg(test)]
-mod tests {
-    use super::EmailClient;
-    use crate::domain::EmailAddress;
-    use claims::{assert_err, assert_ok};
-    use fake::Fake;
-    use fake::faker::internet::en::SafeEmail;
-    use fake::faker::lorem::en::{Paragraph, Sentence};
-    use reqwest::Url;
-    use secrecy::SecretString;
-    use wiremock::matchers::{any, header, header_exists, method, path};
-    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
-
-    struct SendEmailBodyMatcher;
-
-    impl wiremock::Match for SendEmailBodyMatcher {
-        fn matches(&self, request: &Request) -> bool {
-            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
-
-            if let Ok(body) = result {
-                body.get("From").is_some()
-                    && body.get("To").is_some()
-                    && body.get("Subject").is_some()
-                    && body.get("Html").is_some()
-                    && body.get("Text").is_some()
-            } else {
-                false
-            }
-        }
-    }
-
-    /// Generate a random email subject
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
-    /// Generate a random email content
-    fn content() -> String {
-        Paragraph(1..10).fake()
-    }
-    /// Generate a random subscriber email
-    fn email() -> EmailAddress {
-        EmailAddress::parse(SafeEmail().fake()).unwrap()
-    }
-
-    fn url(uri: String) -> Url {
-        Url::parse(uri.as_str()).expect("Failed to parse URI")
-    }
-
-    fn email_client(base_url: Url) -> EmailClient {
-        EmailClient::new(
-            base_url,
-            email(),
-            SecretString::from("secret-token"),
-            std::time::Duration::from_millis(200),
-        )
-    }
-
-    #[tokio::test]
-    async fn send_email_sends_the_expected_request() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(url(mock_server.uri()));
-
-        Mock::given(header_exists("Authorization"))
-            .and(header("Content-Type", "application/json"))
-            .and(path("/emails"))
-            .and(method("POST"))
-            .and(SendEmailBodyMatcher)
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-        // Act
-        let _ = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-    }
-
-    #[tokio::test]
-    async fn send_email_succeeds_if_the_server_returns_200() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(url(mock_server.uri()));
-
-        // We do not copy in all the matchers we have in the other test.
-        // The purpose of this test is not to assert on the request we
-        // are sending out!
-        // We add the bare minimum needed to trigger the path we want
-        // to test in `send_email`.
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-        // Act
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-        // Assert
-        assert_ok!(outcome);
-    }
-
-    #[tokio::test]
-    async fn send_email_fails_if_the_server_returns_500() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(url(mock_server.uri()));
-
-        // We do not copy in all the matchers we have in the other test.
-        // The purpose of this test is not to assert on the request we
-        // are sending out!
-        // We add the bare minimum needed to trigger the path we want
-        // to test in `send_email`.
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(500))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-        // Act
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-        // Assert
-        assert_err!(outcome);
-    }
-
-    #[tokio::test]
-    async fn send_email_times_out_if_the_server_takes_too_long() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(url(mock_server.uri()));
-
-        // We do not copy in all the matchers we have in the other test.
-        // The purpose of this test is not to assert on the request we
-        // are sending out!
-        // We add the bare minimum needed to trigger the path we want
-        // to test in `send_email`.
-        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(15));
-        Mock::given(any())
-            .respond_with(response)
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-        // Act
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-        // Assert
-        assert_err!(outcome);
-    }
-}
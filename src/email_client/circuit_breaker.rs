@@ -0,0 +1,144 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tracks the health of an email provider.
+///
+/// The breaker opens after `failure_threshold` consecutive failures: the provider
+/// is then skipped until `cooldown` has elapsed, after which a single trial request
+/// is let through (half-open). Its outcome closes the breaker or opens it again.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Closed {
+        consecutive_failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A trial request is in flight. Another one is let through after
+    /// `trial_expires_at` if it never reports back.
+    HalfOpen {
+        trial_expires_at: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed {
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    /// Whether a request may be sent to the provider right now.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until }
+            | State::HalfOpen {
+                trial_expires_at: until,
+            } if now < until => false,
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen {
+                    trial_expires_at: now + self.cooldown,
+                };
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Closed { .. }) {
+            tracing::info!("Circuit breaker closed");
+        }
+        *state = State::Closed {
+            consecutive_failures: 0,
+        };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let consecutive_failures = match *state {
+            State::Closed { consecutive_failures } => consecutive_failures + 1,
+            State::HalfOpen { .. } => self.failure_threshold,
+            State::Open { .. } => return,
+        };
+        *state = if consecutive_failures >= self.failure_threshold {
+            tracing::warn!(consecutive_failures, "Circuit breaker opened");
+            State::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            State::Closed { consecutive_failures }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitBreaker;
+    use std::time::Duration;
+
+    #[test]
+    fn the_breaker_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        for _ in 0..2 {
+            breaker.record_failure();
+            assert!(breaker.try_acquire());
+        }
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn a_single_trial_is_let_through_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure();
+        assert!(breaker.try_acquire());
+        breaker.record_success();
+        assert!(breaker.try_acquire());
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn no_other_request_is_let_through_while_the_trial_is_in_flight() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn a_failed_trial_opens_the_breaker_again() {
+        let breaker = CircuitBreaker::new(3, Duration::from_millis(50));
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+    }
+}
@@ -0,0 +1,510 @@
+use super::{CircuitBreaker, EmailError, EmailMessage, EmailTransport};
+use crate::domain::EmailAddress;
+use async_trait::async_trait;
+use std::time::{Duration, Instant};
+use tracing::field::{Empty, display};
+
+/// A named email provider, along with the breaker tracking its health.
+pub struct EmailProvider {
+    pub name: String,
+    pub transport: Box<dyn EmailTransport>,
+    pub circuit_breaker: CircuitBreaker,
+}
+
+/// Tries providers in order, moving on to the next one when a provider is
+/// unavailable (unreachable, 5xx, ...) or its circuit breaker is open.
+/// Failures after which the email may have gone out anyway, e.g. timeouts, are
+/// returned straight away rather than risk sending it twice, as are permanent
+/// failures, e.g. a rejected recipient.
+pub struct FailoverEmailClient {
+    providers: Vec<EmailProvider>,
+}
+
+impl FailoverEmailClient {
+    pub fn new(providers: Vec<EmailProvider>) -> Self {
+        Self { providers }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for FailoverEmailClient {
+    #[tracing::instrument(name = "Send email", skip_all, fields(email.provider = Empty))]
+    async fn send_email(
+        &self,
+        recipient: &EmailAddress,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: Option<&str>,
+    ) -> Result<(), EmailError> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            if !provider.circuit_breaker.try_acquire() {
+                tracing::info!(provider = %provider.name, "Skipping email provider, its circuit breaker is open");
+                continue;
+            }
+            let start = Instant::now();
+            let outcome = provider
+                .transport
+                .send_email(recipient, subject, html_content, text_content, unsubscribe_link)
+                .await;
+            record_sends(
+                &provider.name,
+                "single",
+                start.elapsed(),
+                std::slice::from_ref(&outcome),
+            );
+            match outcome {
+                Ok(()) => {
+                    provider.circuit_breaker.record_success();
+                    tracing::Span::current().record("email.provider", display(&provider.name));
+                    return Ok(());
+                }
+                Err(e) if e.is_known_undelivered() => {
+                    provider.circuit_breaker.record_failure();
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        provider = %provider.name,
+                        "Email provider is unavailable, falling back to the next one",
+                    );
+                    last_error = Some(e);
+                }
+                Err(e) if e.is_transient() => {
+                    provider.circuit_breaker.record_failure();
+                    return Err(e);
+                }
+                Err(e) => {
+                    // The provider answered, it is healthy even though it refused this message.
+                    provider.circuit_breaker.record_success();
+                    if e.is_unauthorized() {
+                        report_unauthorized(&provider.name, &e);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(EmailError::NoProviderAvailable))
+    }
+
+    /// Messages the current provider is known not to have sent are handed to the
+    /// next one, the others keep the outcome they already have.
+    #[tracing::instrument(name = "Send email batch", skip_all, fields(n_messages = messages.len()))]
+    async fn send_batch(&self, messages: &[EmailMessage<'_>]) -> Vec<Result<(), EmailError>> {
+        let mut outcomes: Vec<Option<Result<(), EmailError>>> = messages.iter().map(|_| None).collect();
+        let mut pending: Vec<usize> = (0..messages.len()).collect();
+        for provider in &self.providers {
+            if pending.is_empty() {
+                break;
+            }
+            if !provider.circuit_breaker.try_acquire() {
+                tracing::info!(provider = %provider.name, "Skipping email provider, its circuit breaker is open");
+                continue;
+            }
+            let batch: Vec<_> = pending.iter().map(|&i| messages[i]).collect();
+            let start = Instant::now();
+            let batch_outcomes = provider.transport.send_batch(&batch).await;
+            record_sends(&provider.name, "batch", start.elapsed(), &batch_outcomes);
+            let mut still_pending = Vec::new();
+            let mut n_delivered = 0;
+            let mut is_healthy = true;
+            for (i, outcome) in pending.into_iter().zip(batch_outcomes) {
+                match outcome {
+                    Err(e) if e.is_known_undelivered() => {
+                        still_pending.push(i);
+                        outcomes[i] = Some(Err(e));
+                    }
+                    Ok(()) => {
+                        n_delivered += 1;
+                        outcomes[i] = Some(Ok(()));
+                    }
+                    Err(e) => {
+                        is_healthy &= !e.is_transient();
+                        if e.is_unauthorized() {
+                            report_unauthorized(&provider.name, &e);
+                        }
+                        outcomes[i] = Some(Err(e));
+                    }
+                }
+            }
+            if still_pending.is_empty() && is_healthy {
+                provider.circuit_breaker.record_success();
+            } else {
+                provider.circuit_breaker.record_failure();
+                tracing::warn!(
+                    provider = %provider.name,
+                    n_failed = still_pending.len(),
+                    "Email provider is unavailable, falling back to the next one",
+                );
+            }
+            tracing::info!(provider = %provider.name, n_delivered, "Delivered part of an email batch");
+            pending = still_pending;
+        }
+        outcomes
+            .into_iter()
+            .map(|outcome| outcome.unwrap_or(Err(EmailError::NoProviderAvailable)))
+            .collect()
+    }
+}
+
+/// Nothing goes through this provider until its credentials are fixed: page someone.
+fn report_unauthorized(provider: &str, e: &EmailError) {
+    tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        provider,
+        "The email provider rejected our credentials",
+    );
+}
+
+/// Outcomes are counted per message, the latency per request to the provider.
+fn record_sends(provider: &str, operation: &'static str, elapsed: Duration, outcomes: &[Result<(), EmailError>]) {
+    metrics::histogram!(
+        "email_send_duration_seconds",
+        "provider" => provider.to_owned(),
+        "operation" => operation,
+    )
+    .record(elapsed.as_secs_f64());
+    for outcome in outcomes {
+        let outcome = match outcome {
+            Ok(()) => "delivered",
+            Err(e) if e.is_unauthorized() => "unauthorized",
+            Err(e) if e.is_transient() => "unavailable",
+            Err(_) => "rejected",
+        };
+        metrics::counter!("email_sends_total", "provider" => provider.to_owned(), "outcome" => outcome).increment(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailProvider, FailoverEmailClient};
+    use crate::domain::EmailAddress;
+    use crate::email_client::{CircuitBreaker, EmailClient, EmailMessage, EmailTransport, RateLimiter, RetryPolicy};
+    use claims::{assert_err, assert_ok};
+    use reqwest::Url;
+    use secrecy::SecretString;
+    use std::time::Duration;
+    use wiremock::matchers::{any, body_partial_json, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn email() -> EmailAddress {
+        EmailAddress::parse("ursula_le_guin@gmail.com".into()).unwrap()
+    }
+
+    fn provider(name: &str, server: &MockServer) -> EmailProvider {
+        provider_at(name, &server.uri())
+    }
+
+    fn provider_at(name: &str, base_url: &str) -> EmailProvider {
+        provider_with_retries(name, base_url, RetryPolicy::no_retries())
+    }
+
+    fn provider_with_retries(name: &str, base_url: &str, retry_policy: RetryPolicy) -> EmailProvider {
+        let transport = EmailClient::new(
+            Url::parse(base_url).unwrap(),
+            email(),
+            SecretString::from("secret-token"),
+            Duration::from_millis(200),
+            retry_policy,
+            RateLimiter::new(1000, 1000),
+        );
+        EmailProvider {
+            name: name.into(),
+            transport: Box::new(transport),
+            circuit_breaker: CircuitBreaker::new(2, Duration::from_secs(60)),
+        }
+    }
+
+    async fn send(email_client: &FailoverEmailClient) -> Result<(), crate::email_client::EmailError> {
+        email_client
+            .send_email(&email(), "Subject", "<p>Hi</p>", "Hi", None)
+            .await
+    }
+
+    #[tokio::test]
+    async fn the_next_provider_is_used_when_the_first_one_fails() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_timeout_is_not_retried_with_another_provider() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+
+        // The primary provider may well send the email after we gave up on it
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        assert!(assert_err!(outcome).is_transient());
+    }
+
+    #[tokio::test]
+    async fn a_timeout_followed_by_a_server_error_is_not_retried_with_another_provider() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let retry_policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+            jitter: false,
+        };
+        let email_client = FailoverEmailClient::new(vec![
+            provider_with_retries("primary", &primary.uri(), retry_policy),
+            provider("fallback", &fallback),
+        ]);
+
+        // The first attempt may have gone through, whatever the second one says
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        let e = assert_err!(outcome);
+        assert!(e.is_transient());
+        assert!(!e.is_known_undelivered());
+    }
+
+    #[tokio::test]
+    async fn the_next_provider_is_used_when_the_first_one_is_unreachable() {
+        // Arrange
+        // Nothing listens on the primary provider's port anymore
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let primary = provider_at("primary", &format!("http://127.0.0.1:{port}"));
+        let fallback = MockServer::start().await;
+        let email_client = FailoverEmailClient::new(vec![primary, provider("fallback", &fallback)]);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn authentication_failures_are_not_retried_with_another_provider() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        assert!(assert_err!(outcome).is_unauthorized());
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried_with_another_provider() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_provider_is_skipped_once_its_circuit_breaker_opens() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+
+        // The breaker opens after 2 failures
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(4)
+            .mount(&fallback)
+            .await;
+
+        for _ in 0..4 {
+            // Act
+            let outcome = send(&email_client).await;
+
+            // Assert
+            assert_ok!(outcome);
+        }
+    }
+
+    #[tokio::test]
+    async fn sending_fails_when_every_provider_fails() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(502))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = send(&email_client).await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn only_messages_that_failed_transiently_are_sent_to_the_next_provider() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        // Without a batch endpoint, the primary provider sends messages one by one
+        let email_client =
+            FailoverEmailClient::new(vec![provider("primary", &primary), provider("fallback", &fallback)]);
+        let recipients: Vec<_> = ["ok@example.com", "down@example.com", "rejected@example.com"]
+            .into_iter()
+            .map(|e| EmailAddress::parse(e.into()).unwrap())
+            .collect();
+        let messages: Vec<_> = recipients
+            .iter()
+            .map(|recipient| EmailMessage {
+                recipient,
+                subject: "Subject",
+                html_content: "<p>Hi</p>",
+                text_content: "Hi",
+                unsubscribe_link: None,
+            })
+            .collect();
+
+        Mock::given(path("/emails/batch"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&primary)
+            .await;
+        Mock::given(body_partial_json(serde_json::json!({"To": "down@example.com"})))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(body_partial_json(serde_json::json!({"To": "rejected@example.com"})))
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(path("/emails"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcomes = email_client.send_batch(&messages).await;
+
+        // Assert
+        assert_ok!(&outcomes[0]);
+        assert_ok!(&outcomes[1]);
+        assert_err!(&outcomes[2]);
+    }
+}
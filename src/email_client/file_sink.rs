@@ -0,0 +1,83 @@
+use super::{EmailError, EmailTransport, build_message};
+use crate::domain::EmailAddress;
+use async_trait::async_trait;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::Path;
+
+/// Writes every email as an `.eml` file in a directory instead of sending it.
+/// Meant for local development, where no provider is available.
+pub struct FileEmailClient {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    sender: EmailAddress,
+}
+
+impl FileEmailClient {
+    pub fn new(directory: impl AsRef<Path>, sender: EmailAddress) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory),
+            sender,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for FileEmailClient {
+    #[tracing::instrument(name = "Writing email to the file sink", skip_all, fields(email_id = tracing::field::Empty))]
+    async fn send_email(
+        &self,
+        recipient: &EmailAddress,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: Option<&str>,
+    ) -> Result<(), EmailError> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_link,
+        )?;
+        let email_id = self.transport.send(message).await?;
+        tracing::Span::current().record("email_id", tracing::field::display(email_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileEmailClient;
+    use crate::domain::EmailAddress;
+    use crate::email_client::EmailTransport;
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn send_email_writes_one_eml_file_per_message() {
+        // Arrange
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let sender = EmailAddress::parse("sender@example.com".into()).unwrap();
+        let email_client = FileEmailClient::new(&directory, sender).unwrap();
+        let recipient = EmailAddress::parse("ursula_le_guin@gmail.com".into()).unwrap();
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient, "Issue #1", "<p>Hello</p>", "Hello", None)
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.contains("Subject: Issue #1"));
+        assert!(contents.contains("To: ursula_le_guin@gmail.com"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
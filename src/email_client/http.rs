@@ -0,0 +1,872 @@
+use super::rate_limiter::RateLimiter;
+use super::retry::{RetryPolicy, is_retryable_error, is_retryable_status, retry_after};
+use super::{EmailError, EmailMessage, EmailTransport, send_one_by_one};
+use crate::domain::EmailAddress;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode, Url};
+use secrecy::{ExposeSecret, SecretString};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::field::{Empty, display};
+use uuid::Uuid;
+
+/// Providers such as Resend drop requests repeating a key they have seen in the last 24 hours.
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Delivers emails through the provider's JSON-over-HTTP API (e.g. Resend).
+pub struct EmailClient {
+    http_client: Client,
+    base_url: Url,
+    sender: EmailAddress,
+    authorization_token: SecretString,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    batch_size: usize,
+    /// Cleared the first time the provider tells us it has no batch endpoint.
+    supports_batches: AtomicBool,
+}
+
+/// Resend accepts up to 100 emails per batch.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+impl EmailClient {
+    pub fn new(
+        base_url: Url,
+        sender: EmailAddress,
+        authorization_token: SecretString,
+        timeout: std::time::Duration,
+        retry_policy: RetryPolicy,
+        rate_limiter: RateLimiter,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            sender,
+            authorization_token,
+            retry_policy,
+            rate_limiter,
+            batch_size: DEFAULT_BATCH_SIZE,
+            supports_batches: AtomicBool::new(true),
+        }
+    }
+
+    /// Cap the number of emails per batch request. Batching is disabled below 2.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// A single POST to the provider.
+    #[tracing::instrument(
+        name = "Email API request",
+        skip_all,
+        fields(attempt = attempt, rate_limit.wait_ms = Empty, http.status_code = Empty)
+    )]
+    async fn attempt<T>(
+        &self,
+        url: &Url,
+        request_body: &T,
+        idempotency_key: &str,
+        attempt: u32,
+    ) -> Result<(), AttemptError>
+    where
+        T: serde::Serialize + ?Sized + Sync,
+    {
+        let wait = self.rate_limiter.acquire().await;
+        tracing::Span::current().record("rate_limit.wait_ms", wait.as_millis() as u64);
+        metrics::histogram!("email_rate_limit_wait_seconds").record(wait.as_secs_f64());
+        let response = self
+            .http_client
+            .post(url.clone())
+            .bearer_auth(self.authorization_token.expose_secret())
+            .header(IDEMPOTENCY_KEY, idempotency_key)
+            .json(request_body)
+            .send()
+            .await
+            .map_err(|e| AttemptError {
+                retryable: is_retryable_error(&e),
+                retry_after: None,
+                // Past the connection, the provider may have taken the request before it failed.
+                maybe_delivered: !e.is_connect(),
+                source: e,
+            })?;
+        let status = response.status();
+        tracing::Span::current().record("http.status_code", display(status.as_u16()));
+        let retry_after = retry_after(response.headers());
+        response.error_for_status().map_err(|e| AttemptError {
+            retryable: is_retryable_status(status),
+            retry_after,
+            maybe_delivered: false,
+            source: e,
+        })?;
+        Ok(())
+    }
+}
+
+struct AttemptError {
+    retryable: bool,
+    retry_after: Option<Duration>,
+    /// Whether this attempt, or an earlier one for the same email, failed in a
+    /// way that leaves us unsure whether the provider sent it.
+    maybe_delivered: bool,
+    source: reqwest::Error,
+}
+
+impl From<AttemptError> for EmailError {
+    fn from(e: AttemptError) -> Self {
+        let error = match e.retry_after {
+            Some(retry_after) if e.retryable => EmailError::RetryLater {
+                retry_after,
+                source: e.source,
+            },
+            _ => EmailError::Http(e.source),
+        };
+        if e.maybe_delivered && error.is_known_undelivered() {
+            EmailError::MaybeDelivered(Box::new(error))
+        } else {
+            error
+        }
+    }
+}
+
+impl EmailClient {
+    fn request_body<'a>(&'a self, message: &EmailMessage<'a>) -> SendEmailRequest<'a> {
+        let mut headers = HashMap::new();
+        if let Some(link) = message.unsubscribe_link {
+            headers.insert("List-Unsubscribe", format!("<{link}>"));
+            headers.insert("List-Unsubscribe-Post", "List-Unsubscribe=One-Click".into());
+        }
+        SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: message.recipient.as_ref(),
+            subject: message.subject,
+            html: message.html_content,
+            text: message.text_content,
+            headers,
+        }
+    }
+
+    /// POST `request_body` to `path`, retrying transient failures according to our policy.
+    /// Every attempt carries the same idempotency key, so that the provider sends
+    /// the email once even if an earlier attempt went through without us knowing.
+    async fn post_with_retries<T>(&self, path: &str, request_body: &T) -> Result<(), AttemptError>
+    where
+        T: serde::Serialize + ?Sized + Sync,
+    {
+        // No matter the input
+        let url = self.base_url.join(path).expect("Failed to parse URL");
+        let idempotency_key = Uuid::new_v4().to_string();
+        let mut attempt = 1;
+        let mut maybe_delivered = false;
+        loop {
+            let Err(mut e) = self.attempt(&url, request_body, &idempotency_key, attempt).await else {
+                return Ok(());
+            };
+            maybe_delivered |= e.maybe_delivered;
+            e.maybe_delivered = maybe_delivered;
+            if !e.retryable || attempt >= self.retry_policy.max_attempts {
+                return Err(e);
+            }
+            let delay = match e.retry_after {
+                // We would rather hand the email back, for the caller to requeue it,
+                // than hold on to it for longer than we promised.
+                Some(delay) if delay > self.retry_policy.max_delay => return Err(e),
+                Some(delay) => delay,
+                None => self.retry_policy.backoff(attempt),
+            };
+            tracing::warn!(
+                error.cause_chain = ?e.source,
+                error.message = %e.source,
+                attempt,
+                retry_in_ms = delay.as_millis() as u64,
+                "Email API request failed, retrying",
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send a chunk of messages with a single batch request.
+    /// When the batch as a whole is refused, messages are sent one by one so that
+    /// every recipient gets their own outcome.
+    #[tracing::instrument(name = "Send email batch", skip_all, fields(batch_size = messages.len()))]
+    async fn send_chunk(&self, messages: &[EmailMessage<'_>]) -> Vec<Result<(), EmailError>> {
+        let request_body: Vec<_> = messages.iter().map(|m| self.request_body(m)).collect();
+        let Err(e) = self.post_with_retries("/emails/batch", &request_body).await else {
+            return messages.iter().map(|_| Ok(())).collect();
+        };
+        match e.source.status() {
+            Some(StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED) => {
+                tracing::warn!("The email provider does not support batches, sending emails one by one");
+                self.supports_batches.store(false, Ordering::Relaxed);
+                send_one_by_one(self, messages).await
+            }
+            Some(status) if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS => {
+                tracing::warn!(
+                    error.message = %e.source,
+                    "The email provider refused the batch, sending emails one by one",
+                );
+                send_one_by_one(self, messages).await
+            }
+            _ => {
+                let e = Arc::new(EmailError::from(e));
+                messages
+                    .iter()
+                    .map(|_| Err(EmailError::BatchFailed(e.clone())))
+                    .collect()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for EmailClient {
+    async fn send_email(
+        &self,
+        recipient: &EmailAddress,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: Option<&str>,
+    ) -> Result<(), EmailError> {
+        let message = EmailMessage {
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_link,
+        };
+        self.post_with_retries("/emails", &self.request_body(&message)).await?;
+        Ok(())
+    }
+
+    async fn send_batch(&self, messages: &[EmailMessage<'_>]) -> Vec<Result<(), EmailError>> {
+        let mut outcomes = Vec::with_capacity(messages.len());
+        for chunk in messages.chunks(self.batch_size.max(1)) {
+            if chunk.len() == 1 || !self.supports_batches.load(Ordering::Relaxed) {
+                outcomes.extend(send_one_by_one(self, chunk).await);
+            } else {
+                outcomes.extend(self.send_chunk(chunk).await);
+            }
+        }
+        outcomes
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    headers: HashMap<&'a str, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailClient;
+    use crate::domain::EmailAddress;
+    use crate::email_client::{EmailMessage, EmailTransport, RateLimiter, RetryPolicy};
+    use claims::{assert_err, assert_ok};
+    use fake::Fake;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use reqwest::Url;
+    use secrecy::SecretString;
+    use std::time::{Duration, Instant};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("Html").is_some()
+                    && body.get("Text").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+    /// Generate a random subscriber email
+    fn email() -> EmailAddress {
+        EmailAddress::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn url(uri: String) -> Url {
+        Url::parse(uri.as_str()).expect("Failed to parse URI")
+    }
+
+    fn email_client(base_url: Url) -> EmailClient {
+        email_client_with_retries(base_url, RetryPolicy::no_retries())
+    }
+
+    fn retrying_email_client(base_url: Url) -> EmailClient {
+        let retry_policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            jitter: false,
+        };
+        email_client_with_retries(base_url, retry_policy)
+    }
+
+    fn email_client_with_retries(base_url: Url, retry_policy: RetryPolicy) -> EmailClient {
+        EmailClient::new(
+            base_url,
+            email(),
+            SecretString::from("secret-token"),
+            std::time::Duration::from_millis(200),
+            retry_policy,
+            RateLimiter::new(1000, 1000),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+
+        Mock::given(header_exists("Authorization"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/emails"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_adds_list_unsubscribe_headers_when_given_a_link() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+
+        Mock::given(body_partial_json(serde_json::json!({
+            "Headers": {
+                "List-Unsubscribe": "<https://example.com/unsubscribe?token=abc>",
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+            }
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                Some("https://example.com/unsubscribe?token=abc"),
+            )
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+
+        // We do not copy in all the matchers we have in the other test.
+        // The purpose of this test is not to assert on the request we
+        // are sending out!
+        // We add the bare minimum needed to trigger the path we want
+        // to test in `send_email`.
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+
+        // We do not copy in all the matchers we have in the other test.
+        // The purpose of this test is not to assert on the request we
+        // are sending out!
+        // We add the bare minimum needed to trigger the path we want
+        // to test in `send_email`.
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+
+        // We do not copy in all the matchers we have in the other test.
+        // The purpose of this test is not to assert on the request we
+        // are sending out!
+        // We add the bare minimum needed to trigger the path we want
+        // to test in `send_email`.
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(15));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_server_errors_until_it_succeeds() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_with_the_same_idempotency_key() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(header_exists("Idempotency-Key"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(header_exists("Idempotency-Key"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_ok!(outcome);
+        let keys: Vec<_> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.headers["Idempotency-Key"].clone())
+            .collect();
+        assert_eq!(keys[0], keys[1]);
+    }
+
+    #[tokio::test]
+    async fn each_email_gets_its_own_idempotency_key() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        // Act
+        for _ in 0..2 {
+            assert_ok!(
+                email_client
+                    .send_email(&email(), &subject(), &content(), &content(), None)
+                    .await
+            );
+        }
+        // Assert
+        let requests = mock_server.received_requests().await.unwrap();
+        assert_ne!(
+            requests[0].headers["Idempotency-Key"],
+            requests[1].headers["Idempotency-Key"]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_after_the_maximum_number_of_attempts() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_client_errors() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_for_retry_after_when_rate_limited() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let start = Instant::now();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_email_hands_the_email_back_if_retry_after_exceeds_the_maximum_delay() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        let e = assert_err!(outcome);
+        assert!(e.is_transient());
+        assert_eq!(e.retry_after(), Some(Duration::from_secs(3600)));
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_after_a_timeout() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(url(mock_server.uri()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_connection_errors() {
+        // Arrange
+        // Grab a free port and release it, so that nobody is listening on it.
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let email_client = retrying_email_client(url(format!("http://{address}")));
+        // Act
+        let start = Instant::now();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+        // Assert
+        assert_err!(outcome);
+        // Two back-offs: 50ms, then 100ms.
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn send_email_queues_requests_beyond_the_rate_limit() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            url(mock_server.uri()),
+            email(),
+            SecretString::from("secret-token"),
+            Duration::from_millis(200),
+            RetryPolicy::no_retries(),
+            RateLimiter::new(10, 2),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let start = Instant::now();
+        let recipient = email();
+        let send = || email_client.send_email(&recipient, "Subject", "<p>Hi</p>", "Hi", None);
+        let outcomes = tokio::join!(send(), send(), send(), send());
+        // Assert
+        assert_ok!(outcomes.0);
+        assert_ok!(outcomes.1);
+        assert_ok!(outcomes.2);
+        assert_ok!(outcomes.3);
+        // Two requests fit in the burst, the other two wait 100ms and 200ms.
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+
+    fn recipients(n: usize) -> Vec<EmailAddress> {
+        (0..n)
+            .map(|i| EmailAddress::parse(format!("subscriber{i}@example.com")).unwrap())
+            .collect()
+    }
+
+    fn messages(recipients: &[EmailAddress]) -> Vec<EmailMessage<'_>> {
+        recipients
+            .iter()
+            .map(|recipient| EmailMessage {
+                recipient,
+                subject: "Subject",
+                html_content: "<p>Hi</p>",
+                text_content: "Hi",
+                unsubscribe_link: None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_all_messages_in_a_single_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+        let recipients = recipients(3);
+
+        Mock::given(path("/emails/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcomes = email_client.send_batch(&messages(&recipients)).await;
+        // Assert
+        assert!(outcomes.iter().all(Result::is_ok));
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let to: Vec<_> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["To"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            to,
+            vec![
+                "subscriber0@example.com",
+                "subscriber1@example.com",
+                "subscriber2@example.com"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_messages_according_to_the_batch_size() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri())).with_batch_size(2);
+        let recipients = recipients(5);
+
+        Mock::given(path("/emails/batch"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        // A batch of one is just an email
+        Mock::given(path("/emails"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcomes = email_client.send_batch(&messages(&recipients)).await;
+        // Assert
+        assert_eq!(outcomes.len(), 5);
+        assert!(outcomes.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn send_batch_falls_back_to_individual_emails_if_batches_are_not_supported() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+        let recipients = recipients(3);
+
+        Mock::given(path("/emails/batch"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/emails"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(6)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let first_outcomes = email_client.send_batch(&messages(&recipients)).await;
+        // The batch endpoint is not tried again
+        let second_outcomes = email_client.send_batch(&messages(&recipients)).await;
+        // Assert
+        assert!(first_outcomes.iter().all(Result::is_ok));
+        assert!(second_outcomes.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_the_outcome_of_each_message_when_the_batch_is_refused() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+        let recipients = recipients(3);
+
+        Mock::given(path("/emails/batch"))
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/emails"))
+            .and(body_partial_json(serde_json::json!({"To": "subscriber1@example.com"})))
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/emails"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcomes = email_client.send_batch(&messages(&recipients)).await;
+        // Assert
+        assert_ok!(&outcomes[0]);
+        assert_err!(&outcomes[1]);
+        assert_ok!(&outcomes[2]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_every_message_when_the_batch_fails() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(url(mock_server.uri()));
+        let recipients = recipients(3);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcomes = email_client.send_batch(&messages(&recipients)).await;
+        // Assert
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes.iter().all(|o| o.as_ref().is_err_and(|e| e.is_transient())));
+    }
+}
@@ -0,0 +1,213 @@
+mod circuit_breaker;
+mod failover;
+mod file_sink;
+mod http;
+mod rate_limiter;
+mod retry;
+mod smtp;
+
+pub use circuit_breaker::CircuitBreaker;
+pub use failover::{EmailProvider, FailoverEmailClient};
+pub use file_sink::FileEmailClient;
+pub use http::EmailClient;
+pub use rate_limiter::RateLimiter;
+pub use retry::RetryPolicy;
+pub use smtp::SmtpEmailClient;
+
+use crate::domain::EmailAddress;
+use crate::routes::error_chain_fmt;
+use async_trait::async_trait;
+use lettre::Message;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use reqwest::StatusCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A single, personalised email in a batch.
+#[derive(Clone, Copy, Debug)]
+pub struct EmailMessage<'a> {
+    pub recipient: &'a EmailAddress,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_link: Option<&'a str>,
+}
+
+/// A backend able to deliver an email on our behalf.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// Send an email to `recipient`.
+    /// When `unsubscribe_link` is set, the message carries `List-Unsubscribe` and
+    /// `List-Unsubscribe-Post` headers so that mail clients can offer one-click
+    /// unsubscription (RFC 8058).
+    async fn send_email(
+        &self,
+        recipient: &EmailAddress,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: Option<&str>,
+    ) -> Result<(), EmailError>;
+
+    /// Send many emails at once. Returns one outcome per message, in order.
+    /// Transports without a batch API send them one after the other.
+    async fn send_batch(&self, messages: &[EmailMessage<'_>]) -> Vec<Result<(), EmailError>> {
+        send_one_by_one(self, messages).await
+    }
+}
+
+async fn send_one_by_one<T>(transport: &T, messages: &[EmailMessage<'_>]) -> Vec<Result<(), EmailError>>
+where
+    T: EmailTransport + ?Sized,
+{
+    let mut outcomes = Vec::with_capacity(messages.len());
+    for message in messages {
+        outcomes.push(
+            transport
+                .send_email(
+                    message.recipient,
+                    message.subject,
+                    message.html_content,
+                    message.text_content,
+                    message.unsubscribe_link,
+                )
+                .await,
+        );
+    }
+    outcomes
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailError {
+    #[error("The email API request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("The SMTP server did not accept the message")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Failed to write the message to the sink directory")]
+    FileSink(#[from] lettre::transport::file::Error),
+    #[error("Failed to build the message")]
+    InvalidMessage(#[from] lettre::error::Error),
+    #[error("Invalid mailbox")]
+    InvalidMailbox(#[from] lettre::address::AddressError),
+    #[error("No email provider is currently available")]
+    NoProviderAvailable,
+    #[error("The batch this email was part of failed")]
+    BatchFailed(#[source] Arc<EmailError>),
+    #[error("The email provider asked us to try again in {retry_after:?}")]
+    RetryLater {
+        retry_after: Duration,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The last attempt failed cleanly, but an earlier one timed out or was cut
+    /// short: the provider may have sent the email already.
+    #[error("An earlier attempt may have delivered the email")]
+    MaybeDelivered(#[source] Box<EmailError>),
+}
+
+impl EmailError {
+    /// Whether the provider failed to handle the message (it is down, overloaded,
+    /// too slow...) rather than refused it, so that another provider might succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            EmailError::Http(e) => retry::is_retryable_error(e) || e.status().is_some_and(retry::is_retryable_status),
+            EmailError::Smtp(e) => !e.is_permanent() && !e.is_client(),
+            EmailError::NoProviderAvailable => true,
+            EmailError::BatchFailed(e) => e.is_transient(),
+            EmailError::RetryLater { .. } => true,
+            EmailError::MaybeDelivered(e) => e.is_transient(),
+            EmailError::FileSink(_) | EmailError::InvalidMessage(_) | EmailError::InvalidMailbox(_) => false,
+        }
+    }
+
+    /// Whether the provider failed before taking the message, e.g. it could not
+    /// be reached or answered with a 5xx. Another provider may send it then,
+    /// while a timeout, on any attempt, leaves us unsure whether it went out already.
+    pub fn is_known_undelivered(&self) -> bool {
+        match self {
+            EmailError::Http(e) => e.is_connect() || e.status().is_some_and(retry::is_retryable_status),
+            // 4xx replies: the server did not accept the message, but might later.
+            EmailError::Smtp(e) => e.is_transient() || is_connection_refused(e),
+            EmailError::NoProviderAvailable | EmailError::RetryLater { .. } => true,
+            EmailError::BatchFailed(e) => e.is_known_undelivered(),
+            EmailError::MaybeDelivered(_)
+            | EmailError::FileSink(_)
+            | EmailError::InvalidMessage(_)
+            | EmailError::InvalidMailbox(_) => false,
+        }
+    }
+
+    /// Whether the provider rejected our credentials. No message goes through
+    /// until someone fixes the configuration.
+    pub fn is_unauthorized(&self) -> bool {
+        match self {
+            EmailError::Http(e) => e
+                .status()
+                .is_some_and(|s| s == StatusCode::UNAUTHORIZED || s == StatusCode::FORBIDDEN),
+            // 530: authentication required, 535: authentication credentials invalid.
+            EmailError::Smtp(e) => e.status().is_some_and(|code| matches!(u16::from(code), 530 | 535)),
+            EmailError::BatchFailed(e) => e.is_unauthorized(),
+            EmailError::MaybeDelivered(e) => e.is_unauthorized(),
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked us to wait before sending the message again, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            EmailError::RetryLater { retry_after, .. } => Some(*retry_after),
+            EmailError::BatchFailed(e) => e.retry_after(),
+            EmailError::MaybeDelivered(e) => e.retry_after(),
+            _ => None,
+        }
+    }
+}
+
+fn is_connection_refused(e: &lettre::transport::smtp::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            return e.kind() == std::io::ErrorKind::ConnectionRefused;
+        }
+        source = e.source();
+    }
+    false
+}
+
+impl std::fmt::Debug for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+const LIST_UNSUBSCRIBE: HeaderName = HeaderName::new_from_ascii_str("List-Unsubscribe");
+const LIST_UNSUBSCRIBE_POST: HeaderName = HeaderName::new_from_ascii_str("List-Unsubscribe-Post");
+
+/// Assemble a `multipart/alternative` MIME message, for the transports speaking raw email.
+fn build_message(
+    sender: &EmailAddress,
+    recipient: &EmailAddress,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    unsubscribe_link: Option<&str>,
+) -> Result<Message, EmailError> {
+    let mut builder = Message::builder()
+        .from(sender.as_ref().parse::<Mailbox>()?)
+        .to(recipient.as_ref().parse::<Mailbox>()?)
+        .subject(subject);
+    if let Some(link) = unsubscribe_link {
+        builder = builder
+            .raw_header(HeaderValue::new(LIST_UNSUBSCRIBE, format!("<{link}>")))
+            .raw_header(HeaderValue::new(
+                LIST_UNSUBSCRIBE_POST,
+                "List-Unsubscribe=One-Click".into(),
+            ));
+    }
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        text_content.to_owned(),
+        html_content.to_owned(),
+    ))?;
+    Ok(message)
+}
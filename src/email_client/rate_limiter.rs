@@ -0,0 +1,85 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket matching our provider's quota.
+///
+/// Callers never fail when the bucket is empty: they reserve the next free slot
+/// and wait for it, so concurrent sends are queued in the order they arrived.
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Negative when slots have been reserved by callers still waiting for them.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            requests_per_second: f64::from(requests_per_second.max(1)),
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until a request may be sent. Returns how long we were queued for.
+    pub async fn acquire(&self) -> Duration {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        wait
+    }
+
+    /// Take a token, possibly one that will only be available in the future,
+    /// and return how long to wait for it.
+    fn reserve(&self) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(self.burst);
+        bucket.last_refill = now;
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.requests_per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn requests_within_the_burst_are_not_queued() {
+        let limiter = RateLimiter::new(1, 3);
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire().await, Duration::ZERO);
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_the_burst_are_spaced_out_by_the_rate() {
+        let limiter = RateLimiter::new(10, 1);
+        limiter.acquire().await;
+        let waits = [limiter.reserve(), limiter.reserve(), limiter.reserve()];
+        let expected = [100, 200, 300].map(Duration::from_millis);
+        for (wait, expected) in waits.iter().zip(expected) {
+            assert!(
+                wait.abs_diff(expected) < Duration::from_millis(5),
+                "{wait:?} vs {expected:?}"
+            );
+        }
+    }
+}
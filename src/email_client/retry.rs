@@ -0,0 +1,116 @@
+use rand::Rng;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::time::Duration;
+
+/// How hard we try before giving up on a send.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, the first one included.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomise delays so that concurrent senders do not retry in lockstep.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Give up after the first failure.
+    pub fn no_retries() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: false,
+        }
+    }
+
+    /// Delay before the attempt following the `attempt`-th failure (1-based):
+    /// `base_delay * 2^(attempt - 1)`, capped at `max_delay`.
+    /// With jitter, the delay is drawn uniformly from its upper half.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        half + rand::rng().random_range(Duration::ZERO..=delay - half)
+    }
+}
+
+/// Whether a failed request may succeed if we try again.
+pub(super) fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+pub(super) fn is_retryable_error(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect()
+}
+
+/// Parse a `Retry-After` header, either in delay-seconds or HTTP-date form.
+pub(super) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.to_utc() - chrono::Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RetryPolicy, retry_after};
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use std::time::Duration;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_until_the_cap() {
+        let policy = policy(false);
+        let delays: Vec<_> = (1..=6).map(|attempt| policy.backoff(attempt).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+    }
+
+    #[test]
+    fn jittered_backoff_stays_in_the_upper_half_of_the_delay() {
+        let jittered = policy(true);
+        for attempt in 1..=6 {
+            let expected = policy(false).backoff(attempt);
+            let delay = jittered.backoff(attempt);
+            assert!(delay >= expected / 2 && delay <= expected, "{delay:?} vs {expected:?}");
+        }
+    }
+
+    #[test]
+    fn retry_after_accepts_delay_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn retry_after_in_the_past_means_now() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn garbage_retry_after_is_ignored() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+}
@@ -0,0 +1,165 @@
+use super::{EmailError, EmailTransport, build_message};
+use crate::domain::EmailAddress;
+use async_trait::async_trait;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+
+/// Delivers emails to an SMTP relay.
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: EmailAddress,
+}
+
+impl SmtpEmailClient {
+    pub fn new(transport: AsyncSmtpTransport<Tokio1Executor>, sender: EmailAddress) -> Self {
+        Self { transport, sender }
+    }
+
+    /// Build a transport for `host:port`.
+    /// Without `require_tls` the connection stays in plain text, which is only
+    /// acceptable for a relay on the local network (or a test stand-in).
+    pub fn transport(
+        host: &str,
+        port: u16,
+        require_tls: bool,
+        credentials: Option<Credentials>,
+        timeout: std::time::Duration,
+    ) -> Result<AsyncSmtpTransport<Tokio1Executor>, EmailError> {
+        let mut builder = if require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        }
+        .port(port)
+        .timeout(Some(timeout));
+        if let Some(credentials) = credentials {
+            builder = builder.credentials(credentials);
+        }
+        Ok(builder.build())
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &EmailAddress,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: Option<&str>,
+    ) -> Result<(), EmailError> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_link,
+        )?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmtpEmailClient;
+    use crate::domain::EmailAddress;
+    use crate::email_client::EmailTransport;
+    use claims::{assert_err, assert_ok};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// A bare-bones SMTP server accepting a single session.
+    /// It accepts every command, answers the end of the DATA section with
+    /// `data_reply` and returns what was sent in it.
+    async fn smtp_stand_in(data_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut data = String::new();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let command = line.to_uppercase();
+                if command.starts_with("DATA") {
+                    writer
+                        .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                        .await
+                        .unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    writer.write_all(data_reply.as_bytes()).await.unwrap();
+                } else if command.starts_with("QUIT") {
+                    let _ = writer.write_all(b"221 Bye\r\n").await;
+                    break;
+                } else {
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                }
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        let transport =
+            SmtpEmailClient::transport("127.0.0.1", port, false, None, std::time::Duration::from_secs(2)).unwrap();
+        SmtpEmailClient::new(transport, EmailAddress::parse("sender@example.com".into()).unwrap())
+    }
+
+    fn recipient() -> EmailAddress {
+        EmailAddress::parse("ursula_le_guin@gmail.com".into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_multipart_message_with_unsubscribe_headers() {
+        // Arrange
+        let (port, server) = smtp_stand_in("250 Queued\r\n").await;
+        let email_client = email_client(port);
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &recipient(),
+                "Issue #1",
+                "<p>Hello</p>",
+                "Hello",
+                Some("https://example.com/unsubscribe?token=abc"),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let data = server.await.unwrap();
+        assert!(data.contains("Subject: Issue #1"));
+        assert!(data.contains("To: ursula_le_guin@gmail.com"));
+        assert!(data.contains("List-Unsubscribe: <https://example.com/unsubscribe?token=abc>"));
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(data.contains("multipart/alternative"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_rejects_the_message() {
+        // Arrange
+        let (port, _server) = smtp_stand_in("554 Transaction failed\r\n").await;
+        let email_client = email_client(port);
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient(), "Issue #1", "<p>Hello</p>", "Hello", None)
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::configuration::Settings;
+use crate::domain::EmailAddress;
+use crate::email_client::EmailTransport;
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::startup::Application;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{Span, field::display};
+use uuid::{ContextV7, Timestamp, Uuid};
+
+/// Emails are marked as failed after this many unsuccessful attempts.
+const MAX_RETRIES: i16 = 5;
+
+/// Queue an email for delivery.
+/// Nothing is sent until `transaction` commits, and the email is sent as soon as
+/// the dispatcher picks it up after that, even if the process crashes in between.
+#[tracing::instrument(name = "Enqueue email in the outbox", skip_all, fields(email_id = tracing::field::Empty))]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &EmailAddress,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let email_id = Uuid::new_v7(Timestamp::now(ContextV7::new()));
+    Span::current().record("email_id", display(email_id));
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (email_id, recipient, subject, html_content, text_content)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        email_id,
+        recipient.as_ref(),
+        subject,
+        html_content,
+        text_content,
+    );
+    transaction.execute(query).await?;
+    Ok(email_id)
+}
+
+pub async fn run_dispatcher_until_stopped(
+    configuration: Settings,
+    email_client: Arc<dyn EmailTransport>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = Application::get_connection_pool(&configuration.database);
+    dispatcher_loop(connection_pool, email_client).await
+}
+
+async fn dispatcher_loop(pool: PgPool, email_client: Arc<dyn EmailTransport>) -> Result<(), anyhow::Error> {
+    loop {
+        match try_dispatch_email(&pool, email_client.as_ref()).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(email_id = tracing::field::Empty), err)]
+pub async fn try_dispatch_email(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, email)) = dequeue_email(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("email_id", display(email.email_id));
+
+    let recipient = match EmailAddress::parse(email.recipient.clone()) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::error!(error.message = %e, "Giving up on an email with an invalid recipient");
+            mark_as_failed(&mut transaction, email.email_id).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    match email_client
+        .send_email(
+            &recipient,
+            &email.subject,
+            &email.html_content,
+            &email.text_content,
+            None,
+        )
+        .await
+    {
+        Ok(()) => mark_as_delivered(&mut transaction, email.email_id).await?,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to dispatch an email from the outbox.",
+            );
+            if email.n_retries + 1 < MAX_RETRIES {
+                reschedule_email(&mut transaction, &email, e.retry_after()).await?;
+            } else {
+                tracing::error!("Giving up on dispatching email after {MAX_RETRIES} attempts.");
+                mark_as_failed(&mut transaction, email.email_id).await?;
+            }
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct OutboxEmail {
+    email_id: Uuid,
+    recipient: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    n_retries: i16,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_email(pool: &PgPool) -> Result<Option<(PgTransaction, OutboxEmail)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let email = sqlx::query_as!(
+        OutboxEmail,
+        r#"
+        SELECT email_id, recipient, subject, html_content, text_content, n_retries
+        FROM email_outbox
+        WHERE delivered_at IS NULL AND failed_at IS NULL AND execute_after <= now()
+        ORDER BY execute_after
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(email.map(|email| (transaction, email)))
+}
+
+/// The content is scrubbed once an email is delivered or abandoned: it can
+/// hold confirmation links, which must not outlive their purpose in the database.
+#[tracing::instrument(skip(transaction))]
+async fn mark_as_delivered(transaction: &mut PgTransaction, email_id: Uuid) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET delivered_at = now(), subject = '', html_content = '', text_content = ''
+        WHERE email_id = $1
+        "#,
+        email_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn mark_as_failed(transaction: &mut PgTransaction, email_id: Uuid) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET failed_at = now(), subject = '', html_content = '', text_content = ''
+        WHERE email_id = $1
+        "#,
+        email_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_email(
+    transaction: &mut PgTransaction,
+    email: &OutboxEmail,
+    retry_after: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    // Back off exponentially between attempts: 2s, 4s, 8s, ... unless the provider asked for longer.
+    let backoff = chrono::Duration::seconds(2i64.pow(email.n_retries as u32 + 1));
+    let backoff = retry_after
+        .and_then(|d| chrono::Duration::from_std(d).ok())
+        .map_or(backoff, |d| d.max(backoff));
+    let query = sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET n_retries = n_retries + 1, execute_after = $2
+        WHERE email_id = $1
+        "#,
+        email.email_id,
+        Utc::now() + backoff,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use sha2::{Digest, Sha256};
+
+/// Identifies the request an idempotency key was first sent with, so that
+/// the key cannot be used to replay its response to a different request.
+pub struct RequestFingerprint(Vec<u8>);
+
+impl RequestFingerprint {
+    pub fn of(request: &impl serde::Serialize) -> Self {
+        let bytes = serde_json::to_vec(request).expect("Request bodies are always serializable");
+        Self(Sha256::digest(bytes).to_vec())
+    }
+}
+
+impl AsRef<[u8]> for RequestFingerprint {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestFingerprint;
+
+    #[test]
+    fn the_same_request_has_the_same_fingerprint() {
+        let a = RequestFingerprint::of(&serde_json::json!({"name": "le guin"}));
+        let b = RequestFingerprint::of(&serde_json::json!({"name": "le guin"}));
+
+        assert_eq!(a.as_ref(), b.as_ref());
+    }
+
+    #[test]
+    fn different_requests_have_different_fingerprints() {
+        let a = RequestFingerprint::of(&serde_json::json!({"name": "le guin"}));
+        let b = RequestFingerprint::of(&serde_json::json!({"name": "butler"}));
+
+        assert_ne!(a.as_ref(), b.as_ref());
+    }
+}
@@ -0,0 +1,66 @@
+use axum::http::HeaderMap;
+
+const MAX_LENGTH: usize = 50;
+
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.is_empty() {
+            return Err("The idempotency key cannot be empty".into());
+        }
+        if s.len() >= MAX_LENGTH {
+            return Err(format!(
+                "The idempotency key must be shorter than {MAX_LENGTH} characters"
+            ));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl From<IdempotencyKey> for String {
+    fn from(k: IdempotencyKey) -> Self {
+        k.0
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Read the optional `Idempotency-Key` header of a request.
+pub fn get_idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, String> {
+    let Some(value) = headers.get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| "The idempotency key must be a valid ASCII string".to_string())?;
+    IdempotencyKey::try_from(value.to_string()).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdempotencyKey, MAX_LENGTH};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_keys_are_rejected() {
+        assert_err!(IdempotencyKey::try_from("".to_string()));
+    }
+
+    #[test]
+    fn keys_that_are_too_long_are_rejected() {
+        assert_err!(IdempotencyKey::try_from("a".repeat(MAX_LENGTH)));
+    }
+
+    #[test]
+    fn a_uuid_is_a_valid_key() {
+        assert_ok!(IdempotencyKey::try_from(uuid::Uuid::new_v4().to_string()));
+    }
+}
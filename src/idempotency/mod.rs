@@ -0,0 +1,9 @@
+mod fingerprint;
+mod key;
+mod persistence;
+
+pub use fingerprint::RequestFingerprint;
+pub use key::{IdempotencyKey, get_idempotency_key};
+pub use persistence::{
+    ANONYMOUS_USER_ID, IDEMPOTENCY_KEY_TTL, IdempotencyError, NextAction, save_response, try_processing,
+};
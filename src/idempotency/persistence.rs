@@ -0,0 +1,198 @@
+use crate::idempotency::{IdempotencyKey, RequestFingerprint};
+use crate::problem::Problem;
+use crate::routes::error_chain_fmt;
+use anyhow::Context;
+use axum::body::{Body, to_bytes};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use sqlx::postgres::types::PgInterval;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Owner of idempotency keys sent by unauthenticated clients (e.g. `POST /subscriptions`).
+/// They all share it: request fingerprints keep clients from replaying each other's responses.
+pub const ANONYMOUS_USER_ID: Uuid = Uuid::nil();
+
+/// Clients retry within minutes: a day is plenty to replay their responses.
+/// Older keys are treated as unused, until the sweeper deletes them.
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("The idempotency key was already used for a different request.")]
+    KeyReused,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IdempotencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for IdempotencyError {
+    fn into_response(self) -> Response {
+        match &self {
+            IdempotencyError::KeyReused => Problem::new(StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            IdempotencyError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+pub enum NextAction {
+    /// No request with this key has been seen before: run the handler inside
+    /// this transaction, then call `save_response`.
+    StartProcessing(Transaction<'static, Postgres>),
+    ReturnSavedResponse(Response),
+}
+
+/// Claim an idempotency key. Concurrent requests with the same key block on the
+/// insert until the first one commits (or rolls back), so they never run twice.
+/// The saved response is only replayed to requests with the same fingerprint.
+/// An expired key is claimed again, as if it had never been used.
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    fingerprint: &RequestFingerprint,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = pool.begin().await.context("Failed to begin a transaction")?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, request_hash, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET
+            request_hash = EXCLUDED.request_hash,
+            created_at = EXCLUDED.created_at,
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL
+        WHERE idempotency.created_at <= now() - $4::interval
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        fingerprint.as_ref(),
+        key_ttl(),
+    );
+    let n_inserted_rows = transaction
+        .execute(query)
+        .await
+        .context("Failed to claim the idempotency key")?
+        .rows_affected();
+    if n_inserted_rows > 0 {
+        return Ok(NextAction::StartProcessing(transaction));
+    }
+    let saved_response = get_saved_response(pool, idempotency_key, user_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+    if saved_response.request_hash != fingerprint.as_ref() {
+        return Err(IdempotencyError::KeyReused);
+    }
+    Ok(NextAction::ReturnSavedResponse(saved_response.response))
+}
+
+fn key_ttl() -> PgInterval {
+    PgInterval::try_from(IDEMPOTENCY_KEY_TTL).expect("The idempotency key TTL fits in an interval")
+}
+
+struct SavedResponse {
+    request_hash: Vec<u8>,
+    response: Response,
+}
+
+async fn get_saved_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<SavedResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            request_hash,
+            response_status_code as "response_status_code!",
+            response_headers as "response_headers!: Vec<HeaderPairRecord>",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2 AND
+            created_at > now() - $3::interval
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        key_ttl(),
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(r) = saved_response else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+    let mut response = Response::builder().status(status_code);
+    for HeaderPairRecord { name, value } in r.response_headers {
+        response = response.header(name, value);
+    }
+    Ok(Some(SavedResponse {
+        request_hash: r.request_hash,
+        response: response.body(Body::from(r.response_body))?,
+    }))
+}
+
+/// Persist the response produced for an idempotency key and commit the
+/// transaction opened by `try_processing`.
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    response: Response,
+) -> Result<Response, anyhow::Error> {
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .context("Failed to read the response body")?;
+    let status_code = parts.status.as_u16() as i16;
+    let headers = parts
+        .headers
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    // `query!` cannot type-check the custom `header_pair` type, hence `query_unchecked!`.
+    let query = sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
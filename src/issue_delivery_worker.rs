@@ -0,0 +1,325 @@
+use crate::configuration::Settings;
+use crate::domain::{EmailAddress, Locale, SubscriptionToken};
+use crate::email_client::{EmailMessage, EmailTransport};
+use crate::startup::Application;
+use crate::templates::{NewsletterEmail, Templates};
+use chrono::Utc;
+use sqlx::{PgExecutor, PgPool};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Span;
+use uuid::Uuid;
+
+/// Deliveries are dropped from the queue after this many failed attempts.
+const MAX_RETRIES: i16 = 5;
+/// Deliveries claimed, and handed over to the email client, at once. Their
+/// outcomes are committed right after, so a crash resends at most one chunk.
+const CHUNK_SIZE: i64 = 10;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    email_client: Arc<dyn EmailTransport>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = Application::get_connection_pool(&configuration.database);
+    let templates = Templates::load(&configuration.templates.directory)?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        templates,
+        configuration.application.base_url,
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    templates: Templates,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(&pool, email_client.as_ref(), &templates, &base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    templates: &Templates,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let tasks = claim_tasks(pool).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", tasks.len());
+
+    // Subscribers may have left the list after the issue was published.
+    let emails: Vec<String> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let recipients = get_confirmed_recipients(pool, &emails).await?;
+    let mut issues = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        let email = match EmailAddress::parse(task.subscriber_email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::error!(
+                    error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid",
+                );
+                delete_task(pool, task).await?;
+                continue;
+            }
+        };
+        let Some(recipient) = recipients.get(&task.subscriber_email) else {
+            tracing::info!(
+                newsletter_issue_id = %task.newsletter_issue_id,
+                "Skipping a subscriber who is no longer confirmed"
+            );
+            delete_task(pool, task).await?;
+            continue;
+        };
+        if let Entry::Vacant(entry) = issues.entry(task.newsletter_issue_id) {
+            entry.insert(get_issue(pool, task.newsletter_issue_id).await?);
+        }
+        // Only the hash of the token is stored, so every email gets a new one.
+        let unsubscribe_token = SubscriptionToken::new();
+        let unsubscribe_link = format!(
+            "{base_url}/subscriptions/unsubscribe?token={}",
+            unsubscribe_token.as_ref()
+        );
+        let issue = &issues[&task.newsletter_issue_id];
+        let rendered = templates.newsletter_email(
+            Locale::parse(&recipient.locale).unwrap_or_default(),
+            &NewsletterEmail {
+                title: &issue.title,
+                html_content: &issue.html_content,
+                text_content: &issue.text_content,
+                unsubscribe_link: &unsubscribe_link,
+            },
+        )?;
+        deliveries.push((task, email, recipient.id, unsubscribe_token, unsubscribe_link, rendered));
+    }
+
+    let (subscriber_ids, unsubscribe_tokens): (Vec<_>, Vec<_>) = deliveries
+        .iter()
+        .map(|(_, _, subscriber_id, unsubscribe_token, ..)| (*subscriber_id, unsubscribe_token))
+        .unzip();
+    store_unsubscribe_tokens(pool, &subscriber_ids, &unsubscribe_tokens).await?;
+
+    let messages: Vec<_> = deliveries
+        .iter()
+        .map(|(_, email, _, _, unsubscribe_link, rendered)| EmailMessage {
+            recipient: email,
+            subject: &rendered.subject,
+            html_content: &rendered.html_content,
+            text_content: &rendered.text_content,
+            unsubscribe_link: Some(unsubscribe_link),
+        })
+        .collect();
+    let outcomes = email_client.send_batch(&messages).await;
+
+    let mut transaction = pool.begin().await?;
+    for ((task, _, _, unsubscribe_token, ..), outcome) in deliveries.iter().zip(outcomes) {
+        if let Err(e) = outcome {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_email = %task.subscriber_email,
+                "Failed to deliver issue to a confirmed subscriber.",
+            );
+            // Nobody received the link: the next attempt comes with a new one.
+            if e.is_known_undelivered() {
+                delete_unsubscribe_token(&mut *transaction, unsubscribe_token).await?;
+            }
+            if task.n_retries + 1 < MAX_RETRIES {
+                reschedule_task(&mut *transaction, task, e.retry_after()).await?;
+                continue;
+            }
+            tracing::error!("Giving up on delivering issue after {MAX_RETRIES} attempts.");
+        }
+        delete_task(&mut *transaction, task).await?;
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+/// Lease a chunk of tasks to this worker, without holding a transaction open
+/// while their emails are sent. Tasks left behind by a crashed worker are
+/// picked up again once their lease runs out: a single chunk is sent well
+/// within it, so no live worker loses its tasks to another one.
+#[tracing::instrument(skip_all)]
+async fn claim_tasks(pool: &PgPool) -> Result<Vec<Task>, anyhow::Error> {
+    let tasks = sqlx::query_as!(
+        Task,
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = now() + interval '10 minutes'
+        WHERE (newsletter_issue_id, subscriber_email) IN (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_delivery_queue
+            WHERE execute_after <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT $1
+        )
+        RETURNING newsletter_issue_id, subscriber_email, n_retries
+        "#,
+        CHUNK_SIZE,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(tasks)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(executor: impl PgExecutor<'_>, task: &Task) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+    );
+    executor.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    executor: impl PgExecutor<'_>,
+    task: &Task,
+    retry_after: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    // Back off exponentially between attempts: 2s, 4s, 8s, ... unless the provider asked for longer.
+    let backoff = chrono::Duration::seconds(2i64.pow(task.n_retries as u32 + 1));
+    let backoff = retry_after
+        .and_then(|d| chrono::Duration::from_std(d).ok())
+        .map_or(backoff, |d| d.max(backoff));
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1, execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        Utc::now() + backoff,
+    );
+    executor.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn store_unsubscribe_tokens(
+    pool: &PgPool,
+    subscriber_ids: &[Uuid],
+    unsubscribe_tokens: &[&SubscriptionToken],
+) -> Result<(), anyhow::Error> {
+    let hashes: Vec<String> = unsubscribe_tokens.iter().map(|t| t.hash()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO unsubscribe_tokens (unsubscribe_token_hash, subscriber_id)
+        SELECT * FROM UNNEST($1::text[], $2::uuid[])
+        "#,
+        &hashes,
+        subscriber_ids,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_unsubscribe_token(
+    executor: impl PgExecutor<'_>,
+    unsubscribe_token: &SubscriptionToken,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM unsubscribe_tokens WHERE unsubscribe_token_hash = $1"#,
+        unsubscribe_token.hash(),
+    );
+    executor.execute(query).await?;
+    Ok(())
+}
+
+struct Recipient {
+    id: Uuid,
+    locale: String,
+}
+
+/// The subscribers who are still confirmed, by email.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_recipients(
+    pool: &PgPool,
+    subscriber_emails: &[String],
+) -> Result<HashMap<String, Recipient>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, locale
+        FROM subscriptions
+        WHERE email = ANY($1) AND status = 'confirmed'
+        "#,
+        subscriber_emails
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let recipient = Recipient {
+                id: r.id,
+                locale: r.locale,
+            };
+            (r.email, recipient)
+        })
+        .collect())
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(issue)
+}
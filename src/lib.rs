@@ -1,6 +1,19 @@
+pub mod authentication;
 pub mod configuration;
+pub mod confirmation_resender;
 pub mod domain;
 pub mod email_client;
+pub mod email_outbox;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod markdown;
+pub mod metrics;
+pub mod openapi;
+pub mod problem;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
+pub mod subscription_sweeper;
 pub mod telemetry;
+pub mod templates;
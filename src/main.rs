@@ -1,16 +1,61 @@
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
+use zero2prod::confirmation_resender::run_resender_until_stopped;
+use zero2prod::email_outbox::run_dispatcher_until_stopped;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
 use zero2prod::startup::Application;
+use zero2prod::subscription_sweeper::run_sweeper_until_stopped;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
+async fn main() -> Result<(), anyhow::Error> {
     let subscriber = get_subscriber("zero2prod".to_string(), "info".to_string(), std::io::stdout);
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read configuration");
 
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    // A single client, so that rate limits and provider health are tracked process-wide.
+    let email_client = configuration.email_client.clone().client();
+
+    let application = Application::build(configuration.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone(), email_client.clone()));
+    let dispatcher_task = tokio::spawn(run_dispatcher_until_stopped(configuration.clone(), email_client));
+    let resender_task = tokio::spawn(run_resender_until_stopped(configuration.clone()));
+    let sweeper_task = tokio::spawn(run_sweeper_until_stopped(configuration));
+
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = dispatcher_task => report_exit("Email outbox dispatcher", o),
+        o = resender_task => report_exit("Confirmation resender", o),
+        o = sweeper_task => report_exit("Subscription sweeper", o),
+    };
 
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use ammonia::Builder;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::sync::LazyLock;
+
+const OPTIONS: Options = Options::ENABLE_TABLES.union(Options::ENABLE_STRIKETHROUGH);
+
+/// Email clients ignore `<style>` blocks, so every element carries its own style.
+const INLINE_STYLES: &[(&str, &str)] = &[
+    ("p", "margin: 0 0 16px;"),
+    ("h1", "margin: 24px 0 16px; font-size: 24px; line-height: 1.25;"),
+    ("h2", "margin: 24px 0 16px; font-size: 20px; line-height: 1.25;"),
+    ("h3", "margin: 24px 0 16px; font-size: 18px; line-height: 1.25;"),
+    ("a", "color: #1a73e8;"),
+    ("ul", "margin: 0 0 16px; padding-left: 24px;"),
+    ("ol", "margin: 0 0 16px; padding-left: 24px;"),
+    (
+        "blockquote",
+        "margin: 0 0 16px; padding: 0 16px; border-left: 4px solid #dddddd; color: #555555;",
+    ),
+    (
+        "pre",
+        "margin: 0 0 16px; padding: 12px; background-color: #f6f8fa; border-radius: 4px; overflow-x: auto;",
+    ),
+    ("code", "font-family: Menlo, Consolas, monospace; font-size: 14px;"),
+    ("img", "max-width: 100%; height: auto;"),
+    ("hr", "margin: 24px 0; border: none; border-top: 1px solid #dddddd;"),
+    ("table", "margin: 0 0 16px; border-collapse: collapse;"),
+    ("th", "padding: 6px 12px; border: 1px solid #dddddd;"),
+    ("td", "padding: 6px 12px; border: 1px solid #dddddd;"),
+];
+
+/// Drops scripts, event handlers and `javascript:` links from the raw HTML
+/// Markdown lets through, along with any `style` the editors wrote themselves.
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    for (tag, style) in INLINE_STYLES {
+        builder.set_tag_attribute_value(*tag, "style", *style);
+    }
+    builder
+});
+
+/// The two alternatives of a multipart email.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EmailBody {
+    pub html: String,
+    pub text: String,
+}
+
+/// Render a newsletter issue written in Markdown.
+#[tracing::instrument(skip_all)]
+pub fn render(markdown: &str) -> EmailBody {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, OPTIONS));
+    EmailBody {
+        html: SANITIZER.clean(&html).to_string(),
+        text: to_plain_text(markdown),
+    }
+}
+
+fn to_plain_text(markdown: &str) -> String {
+    let mut writer = PlainTextWriter::default();
+    for event in Parser::new_ext(markdown, OPTIONS) {
+        writer.handle(event);
+    }
+    writer.text.lines().map(str::trim_end).collect::<Vec<_>>().join("\n")
+}
+
+/// Lays out Markdown the way people write plain text emails: blank lines
+/// between blocks, `-` and `1.` list markers, `>` quotes, indented code and
+/// link targets in parentheses.
+#[derive(Default)]
+struct PlainTextWriter {
+    text: String,
+    /// Written at the start of every line: `> ` per quote, indentation per list item.
+    prefixes: Vec<String>,
+    /// Line breaks to write before the next piece of text.
+    pending_breaks: usize,
+    /// How many prefixes blank lines keep, so that the line before a quote is not quoted.
+    blank_line_depth: Option<usize>,
+    /// Set right after a list marker, so that the item's content stays on its line.
+    after_marker: bool,
+    /// The next number of each open list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// Where the text of each open link, image or heading starts.
+    starts: Vec<usize>,
+    links: Vec<String>,
+    in_code_block: bool,
+    first_cell: bool,
+}
+
+impl PlainTextWriter {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                for (i, line) in text.trim_end_matches('\n').split('\n').enumerate() {
+                    if i > 0 {
+                        self.break_line(1);
+                    }
+                    self.write(line);
+                }
+                self.break_line(1);
+            }
+            Event::Text(text) | Event::Code(text) => self.write(&text),
+            Event::SoftBreak | Event::HardBreak => self.break_line(1),
+            Event::Rule => {
+                self.break_line(2);
+                self.write("---");
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::Table(_) => self.break_line(2),
+            Tag::Heading { .. } => {
+                self.break_line(2);
+                self.flush();
+                self.starts.push(self.text.len());
+            }
+            Tag::BlockQuote(_) => {
+                self.break_line(2);
+                self.prefixes.push("> ".into());
+            }
+            Tag::CodeBlock(_) => {
+                self.break_line(2);
+                self.prefixes.push("    ".into());
+                self.in_code_block = true;
+            }
+            Tag::List(first_number) => {
+                if self.lists.is_empty() {
+                    self.break_line(2);
+                }
+                self.lists.push(first_number);
+            }
+            Tag::Item => {
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".into(),
+                };
+                self.break_line(1);
+                self.write(&marker);
+                self.prefixes.push(" ".repeat(marker.len()));
+                self.after_marker = true;
+            }
+            Tag::TableHead | Tag::TableRow => {
+                self.break_line(1);
+                self.first_cell = true;
+            }
+            Tag::TableCell => {
+                if !self.first_cell {
+                    self.write(" | ");
+                }
+                self.first_cell = false;
+            }
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.flush();
+                self.starts.push(self.text.len());
+                self.links.push(dest_url.into_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(level) => {
+                let start = self.starts.pop().unwrap_or(self.text.len());
+                let width = self.text[start..].chars().count();
+                let underline = match level {
+                    HeadingLevel::H1 => "=",
+                    HeadingLevel::H2 => "-",
+                    _ => return,
+                };
+                self.break_line(1);
+                self.write(&underline.repeat(width));
+            }
+            TagEnd::BlockQuote(_) | TagEnd::Item => {
+                self.prefixes.pop();
+            }
+            TagEnd::CodeBlock => {
+                self.prefixes.pop();
+                self.in_code_block = false;
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::Link | TagEnd::Image => {
+                let start = self.starts.pop().unwrap_or(self.text.len());
+                let Some(url) = self.links.pop() else { return };
+                // Autolinks already show their target.
+                if self.text[start..] != url && !url.is_empty() {
+                    self.write(&format!(" ({url})"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Ask for `n` line breaks before the next piece of text, `2` leaves a blank line.
+    fn break_line(&mut self, n: usize) {
+        if !self.after_marker {
+            self.pending_breaks = self.pending_breaks.max(n);
+            let depth = self.blank_line_depth.unwrap_or(usize::MAX).min(self.prefixes.len());
+            self.blank_line_depth = Some(depth);
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        self.flush();
+        self.text.push_str(s);
+        self.after_marker = false;
+    }
+
+    fn flush(&mut self) {
+        let breaks = std::mem::take(&mut self.pending_breaks);
+        let blank_line_depth = self.blank_line_depth.take().unwrap_or(self.prefixes.len());
+        if self.text.is_empty() {
+            self.text.extend(self.prefixes.iter().map(String::as_str));
+            return;
+        }
+        for i in 0..breaks {
+            let depth = if i + 1 < breaks {
+                blank_line_depth
+            } else {
+                self.prefixes.len()
+            };
+            self.text.push('\n');
+            self.text.extend(self.prefixes[..depth].iter().map(String::as_str));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    const ISSUE: &str = r#"# Issue #1
+
+Welcome to **the first issue** of our newsletter, read it [online](https://example.com/issues/1).
+
+## What's new
+
+- Markdown authoring
+- Plain text alternatives
+  1. derived automatically
+  2. from the same source
+
+> Quotes are
+> kept readable.
+
+```
+fn main() {
+    println!("Hello");
+}
+```
+
+| Feature | Status |
+|---------|--------|
+| Markdown | Done |
+
+---
+
+See you at <https://example.com>!
+"#;
+
+    #[test]
+    fn markdown_issue_snapshot() {
+        let body = render(ISSUE);
+
+        insta::assert_snapshot!("markdown_issue_html", body.html);
+        insta::assert_snapshot!("markdown_issue_text", body.text);
+    }
+
+    #[test]
+    fn elements_are_styled_inline() {
+        let body = render("Some *text*");
+
+        assert_eq!(
+            body.html.trim(),
+            r#"<p style="margin: 0 0 16px;">Some <em>text</em></p>"#
+        );
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let markdown = r#"Hello <script>alert("pwned")</script><img src="https://example.com/a.png" onerror="alert(1)">
+
+<div onclick="alert(1)" style="position: fixed">Click</div>"#;
+
+        let body = render(markdown);
+
+        assert!(!body.html.contains("script"), "{}", body.html);
+        assert!(!body.html.contains("alert"), "{}", body.html);
+        assert!(!body.html.contains("position: fixed"), "{}", body.html);
+        assert!(body.html.contains("Click"));
+    }
+
+    #[test]
+    fn javascript_links_are_removed() {
+        let body = render("[click me](javascript:alert(1))");
+
+        assert!(!body.html.contains("javascript"), "{}", body.html);
+        assert!(body.html.contains("click me"));
+    }
+
+    #[test]
+    fn raw_html_is_left_out_of_the_plain_text() {
+        let body = render("Hello <b>world</b>");
+
+        assert_eq!(body.text, "Hello world");
+    }
+}
@@ -0,0 +1,81 @@
+use crate::startup::AppState;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// From 5ms to 10s, for every `*_duration_seconds` histogram and the time spent waiting on rate limits.
+const DURATION_BUCKETS_SECONDS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// The recorder is process-wide: the first call installs it, the others share it.
+/// It has to be installed before anything is recorded, or the values are lost.
+pub fn prometheus_handle() -> PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Suffix("_duration_seconds".into()), DURATION_BUCKETS_SECONDS)
+                .expect("The histogram buckets are not empty")
+                .set_buckets_for_metric(
+                    Matcher::Full("email_rate_limit_wait_seconds".into()),
+                    DURATION_BUCKETS_SECONDS,
+                )
+                .expect("The histogram buckets are not empty")
+                .install_recorder()
+                .expect("Failed to install the Prometheus recorder")
+        })
+        .clone()
+}
+
+/// Histograms buffer their samples until upkeep, which scrapes alone do not run.
+pub async fn run_upkeep_until_stopped(handle: PrometheusHandle, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        handle.run_upkeep();
+    }
+}
+
+/// Meant for `Router::route_layer`, so that every request has a `MatchedPath`:
+/// labelling unmatched requests with their URI would let clients create series at will.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let path = request.extensions().get::<MatchedPath>().map(|p| p.as_str().to_owned());
+
+    let response = next.run(request).await;
+
+    if let Some(path) = path {
+        let labels = [
+            ("method", method),
+            ("path", path),
+            ("status", response.status().as_u16().to_string()),
+        ];
+        metrics::counter!("http_requests_total", &labels).increment(1);
+        metrics::histogram!("http_request_duration_seconds", &labels).record(start.elapsed().as_secs_f64());
+    }
+    response
+}
+
+/// Serves, in Prometheus' text format:
+/// - `http_requests_total` and `http_request_duration_seconds`, by method, route and status;
+/// - `db_pool_connections`, by state, and `db_pool_max_connections`;
+/// - `subscriptions_created_total`, `subscriptions_confirmed_total` and `subscriptions_unsubscribed_total`;
+/// - `email_sends_total`, by provider and outcome, and `email_send_duration_seconds`, by provider and operation;
+/// - `email_rate_limit_wait_seconds`, the time HTTP requests to email providers were held back by our rate limiter.
+pub async fn render_metrics(State(state): State<AppState>) -> Response {
+    // The pool has no hooks to report its connections, so they are sampled on each scrape.
+    let size = state.db.size() as f64;
+    let idle = state.db.num_idle() as f64;
+    metrics::gauge!("db_pool_connections", "state" => "idle").set(idle);
+    metrics::gauge!("db_pool_connections", "state" => "in_use").set(size - idle);
+    metrics::gauge!("db_pool_max_connections").set(state.db.options().get_max_connections() as f64);
+
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        prometheus_handle().render(),
+    )
+        .into_response()
+}
@@ -0,0 +1,34 @@
+use crate::routes;
+use axum::Json;
+use utoipa::OpenApi;
+
+/// The public API, for our frontend and apps to generate their clients from.
+/// It is derived from the handlers and the types they accept and return.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zero2prod", description = "Subscribe to the newsletter and confirm subscriptions."),
+    paths(routes::health_check, routes::subscribe, routes::confirm),
+    tags(
+        (name = "subscriptions", description = "Signing up for the newsletter."),
+        (name = "health", description = "Liveness checks."),
+    )
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use utoipa::OpenApi;
+
+    // Review the changes to the snapshot with the frontend team: they are changes to our API.
+    #[test]
+    fn the_api_document_matches_the_snapshot() {
+        let document = ApiDoc::openapi().to_pretty_json().unwrap();
+
+        insta::assert_snapshot!("openapi", document);
+    }
+}
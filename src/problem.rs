@@ -0,0 +1,188 @@
+use crate::telemetry::CurrentRequest;
+use axum::body::to_bytes;
+use axum::extract::Request;
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use uuid::Uuid;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Bodies of error responses from axum itself are a line of plain text.
+const MAX_PLAIN_TEXT_ERROR_LENGTH: usize = 64 * 1024;
+
+/// An error response, rendered as RFC 7807 `application/problem+json`.
+/// Every route reports its errors through it, so clients can handle them in one place.
+#[derive(Debug)]
+pub struct Problem {
+    status: StatusCode,
+    detail: String,
+    errors: Vec<FieldError>,
+}
+
+/// A request field that failed validation.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// Identifies the rule the field broke, for clients to show their own message.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// The body of every error response.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[schema(as = Problem)]
+pub struct ProblemDocument<'a> {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    type_: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: &'a str,
+    /// The path of the request that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    /// Identifies the request in our logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [FieldError],
+}
+
+impl Problem {
+    /// `detail` is shown to clients, so it must not say anything about our internals.
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            detail: detail.into(),
+            errors: vec![],
+        }
+    }
+
+    /// Logs the error, chain included, and tells the client nothing about it.
+    pub fn unexpected(error: &impl std::fmt::Debug) -> Self {
+        tracing::error!("Unexpected Error: {error:?}");
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Something went wrong on our side. Please try again later.",
+        )
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let current_request = CurrentRequest::get();
+        let document = ProblemDocument {
+            // The status code says it all, as RFC 7807 intends for `about:blank`.
+            type_: "about:blank",
+            title: self.status.canonical_reason().unwrap_or("Error"),
+            status: self.status.as_u16(),
+            detail: &self.detail,
+            instance: current_request.as_ref().map(|r| r.path.clone()),
+            request_id: current_request.map(|r| r.id.0),
+            errors: &self.errors,
+        };
+        let body = serde_json::to_vec(&document).expect("Problem documents are always serializable");
+        (
+            self.status,
+            [(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON))],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// Rejections from axum's extractors and its 404s and 405s are plain text,
+/// or empty: turn them into problems like the ones our own handlers return.
+pub async fn render_errors_as_problems(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_plain_text = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_none_or(|content_type| content_type.as_bytes().starts_with(b"text/plain"));
+    if !(status.is_client_error() || status.is_server_error()) || !is_plain_text {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let problem = if status.is_server_error() {
+        Problem::unexpected(&format!("{status} response from the framework"))
+    } else {
+        let detail = match to_bytes(body, MAX_PLAIN_TEXT_ERROR_LENGTH).await {
+            Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).into_owned(),
+            _ => status.canonical_reason().unwrap_or("Error").to_owned(),
+        };
+        Problem::new(status, detail)
+    };
+    let mut response = problem.into_response();
+    // Keep headers such as `WWW-Authenticate` and `Allow`.
+    for (name, value) in &parts.headers {
+        if name != CONTENT_TYPE && name != CONTENT_LENGTH {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldError, Problem};
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    async fn document(problem: Problem) -> serde_json::Value {
+        let response = problem.into_response();
+        assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn problems_are_described_by_their_status() {
+        let document = document(Problem::new(StatusCode::GONE, "The link has expired.")).await;
+
+        assert_eq!(
+            document,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Gone",
+                "status": 410,
+                "detail": "The link has expired."
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn field_errors_are_listed() {
+        let problem = Problem::new(StatusCode::BAD_REQUEST, "Invalid name.").with_errors(vec![FieldError {
+            field: "name",
+            code: "empty",
+            message: "Invalid name.".into(),
+        }]);
+
+        let document = document(problem).await;
+
+        assert_eq!(
+            document["errors"],
+            serde_json::json!([{ "field": "name", "code": "empty", "message": "Invalid name." }])
+        );
+    }
+
+    #[tokio::test]
+    async fn unexpected_errors_are_not_disclosed() {
+        let error = anyhow::anyhow!("password authentication failed for user \"postgres\"");
+
+        let document = document(Problem::unexpected(&error)).await;
+
+        assert_eq!(document["status"], 500);
+        assert!(!document.to_string().contains("postgres"));
+    }
+}
@@ -0,0 +1,56 @@
+use crate::authentication::UserId;
+use crate::domain::Locale;
+use crate::problem::Problem;
+use crate::routes::error_chain_fmt;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::Extension;
+use axum::extract::State;
+use axum::response::{Html, IntoResponse, Response};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum DashboardError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DashboardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for DashboardError {
+    fn into_response(self) -> Response {
+        match &self {
+            DashboardError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
+
+#[tracing::instrument(name = "Show admin dashboard", skip(state), fields(user_id = %user_id))]
+pub async fn admin_dashboard(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Html<String>, DashboardError> {
+    let username = get_username(user_id.0, &state.db).await?;
+
+    // Usernames are not validated: the template engine escapes them.
+    let page = state
+        .templates
+        .admin_dashboard_page(Locale::default(), &username)
+        .context("Failed to render the admin dashboard")?;
+    Ok(Html(page))
+}
+
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve a username.")?;
+    Ok(row.username)
+}
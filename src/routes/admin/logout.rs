@@ -0,0 +1,14 @@
+use crate::routes::LoginError;
+use crate::session_state::TypedSession;
+use anyhow::Context;
+use axum::response::Redirect;
+
+#[tracing::instrument(name = "Log out an admin", skip(session))]
+pub async fn log_out(session: TypedSession) -> Result<Redirect, LoginError> {
+    session.log_out().await.context("Failed to clear the session")?;
+    session
+        .insert_flash("You have successfully logged out.")
+        .await
+        .context("Failed to store the flash message in the session")?;
+    Ok(Redirect::to("/login"))
+}
@@ -0,0 +1,7 @@
+mod dashboard;
+mod logout;
+mod newsletter_preview;
+
+pub use dashboard::*;
+pub use logout::*;
+pub use newsletter_preview::*;
@@ -0,0 +1,69 @@
+use crate::authentication::UserId;
+use crate::domain::Locale;
+use crate::problem::Problem;
+use crate::routes::{BodyData, error_chain_fmt};
+use crate::startup::AppState;
+use crate::templates::NewsletterEmail;
+use anyhow::Context;
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+
+#[derive(thiserror::Error)]
+pub enum PreviewError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PreviewError {
+    fn into_response(self) -> Response {
+        match &self {
+            PreviewError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct NewsletterPreview {
+    subject: String,
+    html: String,
+    text: String,
+}
+
+/// Render an issue exactly as subscribers would receive it, without publishing it.
+/// Takes the same body as `POST /newsletters`.
+#[tracing::instrument(name = "Preview a newsletter issue", skip_all, fields(title = %body.title, user_id = %user_id))]
+pub async fn preview_newsletter(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Json(body): Json<BodyData>,
+) -> Result<Json<NewsletterPreview>, PreviewError> {
+    let content = body.content.into_email_body();
+    // Points to the unsubscribe page, but no subscriber has this token.
+    let unsubscribe_link = format!("{}/subscriptions/unsubscribe?token=preview", state.base_url);
+    let email = state
+        .templates
+        .newsletter_email(
+            Locale::default(),
+            &NewsletterEmail {
+                title: &body.title,
+                html_content: &content.html,
+                text_content: &content.text,
+                unsubscribe_link: &unsubscribe_link,
+            },
+        )
+        .context("Failed to render the newsletter issue")?;
+
+    Ok(Json(NewsletterPreview {
+        subject: email.subject,
+        html: email.html_content,
+        text: email.text_content,
+    }))
+}
@@ -1,12 +1,22 @@
-use crate::domain::{SubscriptionToken, TokenError};
+use crate::domain::{ConfirmationOutcome, Locale, SubscriptionToken, TokenError};
+use crate::problem::{PROBLEM_JSON, Problem, ProblemDocument};
+use crate::routes::prefers_json;
 use crate::startup::AppState;
+use axum::Json;
 use axum::extract::{Query, State};
-use axum::http::StatusCode;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ConfirmParameters {
+    /// The token from the confirmation email. Missing tokens are answered like
+    /// malformed ones, with the invalid link page.
+    #[serde(default)]
     token: String,
 }
 
@@ -20,32 +30,120 @@ impl TryFrom<Query<ConfirmParameters>> for SubscriptionToken {
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(state, parameters))]
-pub async fn confirm(State(state): State<AppState>, parameters: Query<ConfirmParameters>) -> StatusCode {
-    let token: SubscriptionToken = match parameters.try_into() {
-        Ok(token) => token,
-        Err(_) => return StatusCode::BAD_REQUEST,
-    };
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct ConfirmationResponse {
+    status: ConfirmationOutcome,
+}
 
-    let subscriber_info = match get_subscriber_info_from_token(&state.db, token.as_ref()).await {
-        Ok(info) => info,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+/// Confirms the subscription a confirmation link was sent for.
+///
+/// Browsers get a page in the subscriber's language, or are redirected to the
+/// configured `confirmation_redirect_url`. API clients asking for JSON get
+/// `{"status": "confirmed"}`, or a problem with the same status code as the page.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(ConfirmParameters),
+    responses(
+        (
+            status = OK,
+            description = "The subscription is confirmed.",
+            content((ConfirmationResponse = "application/json"), (String = "text/html")),
+        ),
+        (
+            status = SEE_OTHER,
+            description = "Browsers are sent to the configured page, with the outcome in its `status` query parameter.",
+            headers(("Location" = String)),
+        ),
+        (
+            status = BAD_REQUEST,
+            description = "The token is malformed.",
+            content((ProblemDocument = PROBLEM_JSON), (String = "text/html")),
+        ),
+        (
+            status = UNAUTHORIZED,
+            description = "The token does not belong to any subscriber.",
+            content((ProblemDocument = PROBLEM_JSON), (String = "text/html")),
+        ),
+        (
+            status = GONE,
+            description = "The link has expired. The subscriber has to sign up again.",
+            content((ProblemDocument = PROBLEM_JSON), (String = "text/html")),
+        ),
+        (status = INTERNAL_SERVER_ERROR, description = "Something went wrong on our side.", body = ProblemDocument, content_type = PROBLEM_JSON),
+    ),
+)]
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(state, headers, parameters))]
+pub async fn confirm(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    parameters: Query<ConfirmParameters>,
+) -> Response {
+    let (status_code, outcome, subscriber_locale) = match confirm_token(&state.db, parameters).await {
+        Ok(result) => result,
+        Err(e) => {
+            let e = anyhow::Error::from(e).context("Failed to confirm a subscription token");
+            return Problem::unexpected(&e).into_response();
+        }
     };
 
-    match subscriber_info {
-        // Non-existing token!
-        None => StatusCode::UNAUTHORIZED,
-        Some(subscriber_info) => {
-            let status = subscriber_info.1;
-            if status == "confirmed" {
-                return StatusCode::OK;
+    if prefers_json(&headers) {
+        let detail = match outcome {
+            ConfirmationOutcome::Confirmed => {
+                return Json(ConfirmationResponse { status: outcome }).into_response();
             }
-            if confirm_subscriber(&state.db, subscriber_info.0).await.is_err() {
-                return StatusCode::INTERNAL_SERVER_ERROR;
+            ConfirmationOutcome::Expired => "The confirmation link has expired.",
+            ConfirmationOutcome::Invalid if status_code == StatusCode::UNAUTHORIZED => {
+                "There is no subscriber associated with the provided token."
             }
-            StatusCode::OK
+            ConfirmationOutcome::Invalid => "The confirmation token is malformed.",
+        };
+        return Problem::new(status_code, detail).into_response();
+    }
+    if let Some(redirect_url) = &state.confirmation_redirect_url {
+        let mut redirect_url = redirect_url.clone();
+        redirect_url.query_pairs_mut().append_pair("status", outcome.as_str());
+        return Redirect::to(redirect_url.as_str()).into_response();
+    }
+
+    // Strangers following a broken link get a page in their browser's language.
+    let locale = subscriber_locale
+        .or_else(|| Locale::from_accept_language(headers.get(ACCEPT_LANGUAGE)?.to_str().ok()?))
+        .unwrap_or_default();
+    match state.templates.confirmation_page(locale, outcome) {
+        Ok(page) => (status_code, Html(page)).into_response(),
+        Err(e) => {
+            let e = anyhow::Error::from(e).context("Failed to render the confirmation page");
+            Problem::unexpected(&e).into_response()
+        }
+    }
+}
+
+/// Returns the locale of the subscriber the token belongs to, if any.
+async fn confirm_token(
+    pool: &PgPool,
+    parameters: Query<ConfirmParameters>,
+) -> Result<(StatusCode, ConfirmationOutcome, Option<Locale>), sqlx::Error> {
+    let token: SubscriptionToken = match parameters.try_into() {
+        Ok(token) => token,
+        Err(_) => return Ok((StatusCode::BAD_REQUEST, ConfirmationOutcome::Invalid, None)),
+    };
+
+    // Non-existing token!
+    let Some(subscriber_info) = get_subscriber_info_from_token(pool, &token).await? else {
+        return Ok((StatusCode::UNAUTHORIZED, ConfirmationOutcome::Invalid, None));
+    };
+    let locale = Locale::parse(&subscriber_info.locale);
+    if subscriber_info.status != "confirmed" {
+        if subscriber_info.expires_at <= Utc::now() {
+            // The link is stale: the subscriber has to sign up again to get a new one.
+            return Ok((StatusCode::GONE, ConfirmationOutcome::Expired, locale));
         }
+        confirm_subscriber(pool, subscriber_info.subscriber_id).await?;
+        metrics::counter!("subscriptions_confirmed_total").increment(1);
     }
+    Ok((StatusCode::OK, ConfirmationOutcome::Confirmed, locale))
 }
 
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
@@ -74,22 +172,29 @@ pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<()
     Ok(())
 }
 
+pub struct SubscriberInfo {
+    pub subscriber_id: Uuid,
+    pub status: String,
+    pub expires_at: DateTime<Utc>,
+    pub locale: String,
+}
+
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
 pub async fn get_subscriber_info_from_token(
     pool: &PgPool,
-    subscription_token: &str,
-) -> Result<Option<(Uuid, String)>, sqlx::Error> {
-    let result = sqlx::query!(
-        "SELECT subscriber_id, status FROM subscription_tokens \
-        WHERE subscription_token = $1",
-        subscription_token,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<SubscriberInfo>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberInfo,
+        "SELECT t.subscriber_id, t.status, t.expires_at, s.locale \
+        FROM subscription_tokens t JOIN subscriptions s ON s.id = t.subscriber_id \
+        WHERE t.subscription_token_hash = $1",
+        subscription_token.hash(),
     )
     .fetch_optional(pool)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-
-    Ok(result.map(|r| (r.subscriber_id, r.status)))
+    })
 }
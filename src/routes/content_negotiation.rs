@@ -0,0 +1,106 @@
+use axum::http::HeaderMap;
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+
+/// Whether the request body is JSON, going by its `Content-Type`.
+pub fn has_json_body(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|h| h.to_str().ok()) else {
+        return false;
+    };
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    media_type == "application/json" || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+/// Whether the client asked for JSON over HTML in its `Accept` header.
+/// Browsers accept `*/*` with a lower quality, so they get HTML, and so do
+/// clients that do not send the header at all.
+pub fn prefers_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(ACCEPT).and_then(|h| h.to_str().ok()) else {
+        return false;
+    };
+    quality(accept, "application/json") > quality(accept, "text/html")
+}
+
+/// The quality of the most specific media range in `accept` that matches `media_type`.
+fn quality(accept: &str, media_type: &str) -> f32 {
+    let wildcard = media_type.split('/').next().map(|t| format!("{t}/*"));
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let range = parts.next()?.trim();
+            let specificity = if range.eq_ignore_ascii_case(media_type) {
+                2
+            } else if wildcard.as_deref().is_some_and(|w| range.eq_ignore_ascii_case(w)) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                return None;
+            };
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            Some((specificity, quality))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map_or(0.0, |(_, quality)| quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_json_body, prefers_json};
+    use axum::http::HeaderMap;
+    use axum::http::header::{ACCEPT, CONTENT_TYPE};
+
+    fn accepting(accept: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, accept.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn api_clients_get_json() {
+        assert!(prefers_json(&accepting("application/json")));
+        assert!(prefers_json(&accepting("application/json, text/html;q=0.5")));
+    }
+
+    #[test]
+    fn browsers_get_html() {
+        assert!(!prefers_json(&accepting(
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+        )));
+    }
+
+    #[test]
+    fn html_wins_ties_and_missing_headers() {
+        assert!(!prefers_json(&accepting("*/*")));
+        assert!(!prefers_json(&accepting("application/json, text/html")));
+        assert!(!prefers_json(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn the_most_specific_range_decides() {
+        assert!(prefers_json(&accepting("application/json, */*;q=0.1")));
+        assert!(!prefers_json(&accepting("application/json;q=0, */*")));
+    }
+
+    #[test]
+    fn json_bodies_are_recognised_by_their_content_type() {
+        let with_content_type = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+            headers
+        };
+
+        assert!(has_json_body(&with_content_type("application/json")));
+        assert!(has_json_body(&with_content_type("Application/JSON; charset=utf-8")));
+        assert!(has_json_body(&with_content_type("application/merge-patch+json")));
+        assert!(!has_json_body(&with_content_type("application/x-www-form-urlencoded")));
+        assert!(!has_json_body(&HeaderMap::new()));
+    }
+}
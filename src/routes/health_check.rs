@@ -1,5 +1,7 @@
 use axum::http::StatusCode;
 
+/// Answers as long as the application is up.
+#[utoipa::path(get, path = "/health-check", tag = "health", responses((status = OK, description = "The application is up.")))]
 pub async fn health_check() -> StatusCode {
     StatusCode::OK
 }
@@ -0,0 +1,93 @@
+use crate::authentication::{AuthError, Credentials, validate_credentials};
+use crate::domain::Locale;
+use crate::problem::Problem;
+use crate::routes::error_chain_fmt;
+use crate::session_state::TypedSession;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::Form;
+use axum::extract::State;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use secrecy::SecretString;
+use tracing::field::display;
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for LoginError {
+    fn into_response(self) -> Response {
+        match &self {
+            LoginError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: SecretString,
+}
+
+pub async fn login_form(State(state): State<AppState>, session: TypedSession) -> Result<Html<String>, LoginError> {
+    let flash_message = match session.take_flash().await {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::warn!("Failed to read flash message from the session: {e:?}");
+            None
+        }
+    };
+
+    let page = state
+        .templates
+        .login_page(Locale::default(), flash_message.as_deref())
+        .context("Failed to render the login page")?;
+    Ok(Html(page))
+}
+
+#[tracing::instrument(
+    name = "Log in an admin",
+    skip(state, session, form),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+    State(state): State<AppState>,
+    session: TypedSession,
+    Form(form): Form<LoginFormData>,
+) -> Result<Redirect, LoginError> {
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password,
+    };
+    tracing::Span::current().record("username", display(&credentials.username));
+
+    match validate_credentials(credentials, &state.db).await {
+        Ok(user_id) => {
+            tracing::Span::current().record("user_id", display(&user_id));
+            session.renew().await.context("Failed to rotate the session id")?;
+            session
+                .insert_user_id(user_id)
+                .await
+                .context("Failed to store the user id in the session")?;
+            Ok(Redirect::to("/admin/dashboard"))
+        }
+        Err(AuthError::InvalidCredentials(e)) => {
+            tracing::debug!("Authentication failed: {e:?}");
+            session
+                .insert_flash("Authentication failed.")
+                .await
+                .context("Failed to store the flash message in the session")?;
+            Ok(Redirect::to("/login"))
+        }
+        Err(AuthError::UnexpectedError(e)) => Err(LoginError::UnexpectedError(e)),
+    }
+}
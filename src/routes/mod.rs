@@ -1,7 +1,19 @@
+mod admin;
 mod confirm_subscriptions;
+mod content_negotiation;
 pub mod health_check;
+mod login;
+pub mod newsletters;
+mod resend_confirmation;
 pub mod subscriptions;
+mod unsubscribe;
 
+pub use admin::*;
 pub use confirm_subscriptions::*;
+pub use content_negotiation::*;
 pub use health_check::*;
+pub use login::*;
+pub use newsletters::*;
+pub use resend_confirmation::*;
 pub use subscriptions::*;
+pub use unsubscribe::*;
@@ -0,0 +1,163 @@
+use crate::authentication::BasicAuthUser;
+use crate::idempotency::{
+    IdempotencyError, NextAction, RequestFingerprint, get_idempotency_key, save_response, try_processing,
+};
+use crate::markdown::{self, EmailBody};
+use crate::problem::Problem;
+use crate::routes::error_chain_fmt;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::Json;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::{ContextV7, Timestamp, Uuid};
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+
+    #[error(transparent)]
+    IdempotencyError(#[from] IdempotencyError),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PublishError {
+    fn into_response(self) -> Response {
+        match self {
+            PublishError::ValidationError(e) => {
+                tracing::debug!("Validation Error: {e:?}");
+                Problem::new(StatusCode::BAD_REQUEST, e)
+            }
+            PublishError::IdempotencyError(e) => return e.into_response(),
+            e @ PublishError::UnexpectedError(_) => Problem::unexpected(&e),
+        }
+        .into_response()
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct BodyData {
+    pub title: String,
+    pub content: Content,
+}
+
+/// Issues are written in Markdown, or as both HTML and plain text.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Content {
+    Markdown { markdown: String },
+    Html { html: String, text: String },
+}
+
+impl Content {
+    pub fn into_email_body(self) -> EmailBody {
+        match self {
+            Content::Markdown { markdown } => markdown::render(&markdown),
+            Content::Html { html, text } => EmailBody { html, text },
+        }
+    }
+}
+
+/// Store the issue and enqueue one delivery task per confirmed subscriber.
+/// Emails are sent by the background worker in `issue_delivery_worker`.
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip_all,
+    fields(title = %body.title, user_id = %user.user_id)
+)]
+pub async fn publish_newsletter(
+    State(state): State<AppState>,
+    user: BasicAuthUser,
+    headers: HeaderMap,
+    Json(body): Json<BodyData>,
+) -> Result<Response, PublishError> {
+    let idempotency_key = get_idempotency_key(&headers).map_err(PublishError::ValidationError)?;
+
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => {
+            match try_processing(&state.db, idempotency_key, user.user_id, &RequestFingerprint::of(&body)).await? {
+                NextAction::StartProcessing(transaction) => transaction,
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            }
+        }
+        None => state
+            .db
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
+    };
+
+    let content = body.content.into_email_body();
+    let issue_id = insert_newsletter_issue(&mut transaction, &body.title, &content.text, &content.html)
+        .await
+        .context("Failed to store newsletter issue details")?;
+
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+
+    let response = StatusCode::ACCEPTED.into_response();
+    match idempotency_key {
+        Some(idempotency_key) => Ok(save_response(transaction, &idempotency_key, user.user_id, response).await?),
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to publish a newsletter issue")?;
+            Ok(response)
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v7(Timestamp::now(ContextV7::new()));
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content,
+        Utc::now()
+    );
+    transaction.execute(query).await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use crate::domain::{EmailAddress, NewSubscriberError};
+use crate::routes::SubscriptionError;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::Form;
+use axum::extract::State;
+use axum::http::StatusCode;
+use sqlx::PgExecutor;
+use uuid::{ContextV7, Timestamp, Uuid};
+
+#[derive(serde::Deserialize)]
+pub struct ResendConfirmationForm {
+    email: String,
+}
+
+/// Ask for a fresh confirmation link to be sent to a pending subscriber.
+/// The request is only recorded here and handled by `confirmation_resender`:
+/// the response, and the time it takes, are the same whether the address is
+/// pending, confirmed, unknown or rate limited, so the endpoint cannot be
+/// used to enumerate subscribers.
+#[tracing::instrument(name = "Resend a confirmation email", skip(state, form), fields(subscriber_email = %form.email))]
+pub async fn resend_confirmation(
+    State(state): State<AppState>,
+    Form(form): Form<ResendConfirmationForm>,
+) -> Result<StatusCode, SubscriptionError> {
+    let email = EmailAddress::parse(form.email).map_err(|e| vec![NewSubscriberError::from(e)])?;
+
+    request_resend(state.db.as_ref(), &email)
+        .await
+        .context("Failed to record the confirmation resend request")?;
+
+    Ok(StatusCode::OK)
+}
+
+/// `confirmation_resender` sends the email, unless the address is over its hourly quota.
+#[tracing::instrument(name = "Request confirmation resend", skip(executor, email))]
+pub async fn request_resend(executor: impl PgExecutor<'_>, email: &EmailAddress) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO confirmation_resends (resend_id, email, requested_at) VALUES ($1, $2, now())"#,
+        Uuid::new_v7(Timestamp::now(ContextV7::new())),
+        email.as_ref()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
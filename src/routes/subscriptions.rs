@@ -1,10 +1,20 @@
-use crate::domain::{EmailAddress, NewSubscriber, SubscriberName, SubscriptionToken};
-use crate::email_client::EmailClient;
+use crate::domain::{EmailAddress, Locale, NewSubscriber, NewSubscriberError, SubscriptionToken};
+use crate::email_outbox::enqueue_email;
+use crate::idempotency::{
+    ANONYMOUS_USER_ID, IdempotencyError, NextAction, RequestFingerprint, get_idempotency_key, save_response,
+    try_processing,
+};
+use crate::problem::{FieldError, PROBLEM_JSON, Problem, ProblemDocument};
+use crate::routes::{has_json_body, prefers_json, request_resend};
 use crate::startup::AppState;
+use crate::templates::{ConfirmationEmail, Templates};
 use anyhow::Context;
+use axum::extract::{FromRequest, Request};
+use axum::http::HeaderMap;
+use axum::http::header::ACCEPT_LANGUAGE;
 use axum::response::{IntoResponse, Response};
-use axum::{Form, extract::State, http::StatusCode};
-use chrono::Utc;
+use axum::{Form, Json, extract::State, http::StatusCode};
+use chrono::{DateTime, Utc};
 use sqlx::{Executor, Postgres, Transaction};
 use uuid::{ContextV7, Timestamp, Uuid};
 
@@ -13,6 +23,12 @@ pub enum SubscriptionError {
     #[error("{0}")]
     ValidationError(String),
 
+    #[error("The request has invalid fields.")]
+    InvalidFields(Vec<FieldError>),
+
+    #[error(transparent)]
+    IdempotencyError(#[from] IdempotencyError),
+
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -25,124 +41,320 @@ impl std::fmt::Debug for SubscriptionError {
 
 impl IntoResponse for SubscriptionError {
     fn into_response(self) -> Response {
-        let status = match self {
+        match self {
             SubscriptionError::ValidationError(e) => {
                 tracing::debug!("Validation Error: {e:?}");
-                StatusCode::BAD_REQUEST
+                Problem::new(StatusCode::BAD_REQUEST, e)
             }
-            SubscriptionError::UnexpectedError(e) => {
-                tracing::error!("Unexpected Error: {e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
+            SubscriptionError::InvalidFields(errors) => {
+                tracing::debug!("Validation Errors: {errors:?}");
+                let detail = errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join(" ");
+                Problem::new(StatusCode::BAD_REQUEST, detail).with_errors(errors)
             }
-        };
-        status.into_response()
+            SubscriptionError::IdempotencyError(e) => return e.into_response(),
+            e @ SubscriptionError::UnexpectedError(_) => Problem::unexpected(&e),
+        }
+        .into_response()
     }
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 pub struct NewSubscriptionForm {
     email: String,
     name: String,
+    /// Overrides the languages in the `Accept-Language` header.
+    locale: Option<String>,
 }
 
 impl TryFrom<NewSubscriptionForm> for NewSubscriber {
-    type Error = String;
+    type Error = Vec<NewSubscriberError>;
 
     fn try_from(value: NewSubscriptionForm) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = EmailAddress::parse(value.email)?;
+        NewSubscriber::parse(value.name, value.email)
+    }
+}
+
+impl From<NewSubscriberError> for FieldError {
+    fn from(e: NewSubscriberError) -> Self {
+        Self {
+            field: e.field(),
+            code: e.code(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<Vec<NewSubscriberError>> for SubscriptionError {
+    fn from(errors: Vec<NewSubscriberError>) -> Self {
+        SubscriptionError::InvalidFields(errors.into_iter().map(FieldError::from).collect())
+    }
+}
+
+/// The body of `POST /subscriptions`: an HTML form, or JSON for our apps.
+pub struct NewSubscriptionRequest {
+    form: NewSubscriptionForm,
+    is_json: bool,
+}
+
+impl<S: Send + Sync> FromRequest<S> for NewSubscriptionRequest {
+    type Rejection = Response;
 
-        Ok(Self { name, email })
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if has_json_body(request.headers()) {
+            let Json(form) = Json::from_request(request, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self { form, is_json: true })
+        } else {
+            let Form(form) = Form::from_request(request, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self { form, is_json: false })
+        }
     }
 }
 
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct SubscriptionResponse {
+    /// Always `pending_confirmation`: addresses already on the list get the same answer as new ones.
+    #[schema(value_type = String, example = "pending_confirmation")]
+    status: &'static str,
+}
+
+/// Sends a confirmation email to the new subscriber.
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(
+        description = "Browsers post an HTML form, our apps post JSON.",
+        content(
+            (NewSubscriptionForm = "application/json"),
+            (NewSubscriptionForm = "application/x-www-form-urlencoded"),
+        ),
+    ),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key get the first response back."),
+        ("Accept-Language" = Option<String>, Header, description = "Picks the language of the confirmation email, unless `locale` is set."),
+    ),
+    responses(
+        (
+            status = OK,
+            description = "The subscription is pending confirmation. The body is empty unless the request was JSON or JSON was asked for.",
+            body = SubscriptionResponse,
+        ),
+        (status = BAD_REQUEST, description = "Some fields are invalid.", body = ProblemDocument, content_type = PROBLEM_JSON),
+        (
+            status = UNPROCESSABLE_ENTITY,
+            description = "The body is missing required fields, or the idempotency key was used for another request.",
+            body = ProblemDocument,
+            content_type = PROBLEM_JSON,
+        ),
+        (status = INTERNAL_SERVER_ERROR, description = "Something went wrong on our side.", body = ProblemDocument, content_type = PROBLEM_JSON),
+    ),
+)]
 #[allow(clippy::async_yields_async)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, state),
+    skip(request, state, headers),
     fields(
-        subscriber_email= %form.email,
-        subscriber_name= %form.name
+        subscriber_email= %request.form.email,
+        subscriber_name= %request.form.name
     )
 )]
 pub async fn subscribe(
     State(state): State<AppState>,
-    Form(form): Form<NewSubscriptionForm>,
-) -> Result<StatusCode, SubscriptionError> {
-    let new_subscriber = form.try_into().map_err(SubscriptionError::ValidationError)?;
+    headers: HeaderMap,
+    request: NewSubscriptionRequest,
+) -> Result<Response, SubscriptionError> {
+    let respond_with_json = request.is_json || prefers_json(&headers);
+    let locale = subscriber_locale(request.form.locale.as_deref(), &headers);
+    // The same fields get a different answer depending on the format asked for.
+    let fingerprint = RequestFingerprint::of(&(&request.form, respond_with_json));
+    let new_subscriber: NewSubscriber = request.form.try_into()?;
+    let idempotency_key = get_idempotency_key(&headers).map_err(SubscriptionError::ValidationError)?;
 
-    let mut transaction = state
-        .db
-        .begin()
-        .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => {
+            match try_processing(&state.db, idempotency_key, ANONYMOUS_USER_ID, &fingerprint).await? {
+                NextAction::StartProcessing(transaction) => transaction,
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            }
+        }
+        None => state
+            .db
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
+    };
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    // New and existing subscribers get exactly the same answer, in JSON too,
+    // so this endpoint cannot be used to find out who is on the list.
+    let inserted_subscriber_id = insert_subscriber(&mut transaction, &new_subscriber, locale)
         .await
         .context("Failed to insert new subscriber in the database")?;
+    if let Some(subscriber_id) = inserted_subscriber_id {
+        let subscription_token = SubscriptionToken::new();
+        let expires_at = Utc::now()
+            + chrono::Duration::from_std(state.subscription_tokens.ttl())
+                .context("The confirmation token TTL is out of range")?;
 
-    let subscription_token = SubscriptionToken::new();
+        store_token(&mut transaction, subscriber_id, &subscription_token, expires_at)
+            .await
+            .context("Failed to store the confirmation token for a new subscriber")?;
 
-    store_token(&mut transaction, subscriber_id, subscription_token.as_ref())
+        enqueue_confirmation_email(
+            &mut transaction,
+            &state.templates,
+            &new_subscriber.email,
+            new_subscriber.name.as_ref(),
+            locale,
+            state.base_url,
+            subscription_token.as_ref(),
+        )
         .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
-
-    transaction
-        .commit()
+        .context("Failed to enqueue the confirmation email")?;
+    } else if restart_confirmation(&mut transaction, &new_subscriber.email)
         .await
-        .context("Failed to commit SQL transaction to store a new subscriber")?;
+        .context("Failed to restart the confirmation of an existing subscriber")?
+    {
+        // Anyone can post an address again and again: existing subscribers get
+        // their new link through the resender, which enforces the hourly quota.
+        request_resend(&mut *transaction, &new_subscriber.email)
+            .await
+            .context("Failed to record the confirmation resend request")?;
+    }
 
-    send_confirmation_email(
-        &state.email_client,
-        new_subscriber.email,
-        state.base_url,
-        subscription_token.as_ref(),
-    )
-    .await
-    .context("Failed to send confirmation email")?;
+    let response = if respond_with_json {
+        Json(SubscriptionResponse {
+            status: "pending_confirmation",
+        })
+        .into_response()
+    } else {
+        StatusCode::OK.into_response()
+    };
+    let response = match idempotency_key {
+        Some(idempotency_key) => save_response(transaction, &idempotency_key, ANONYMOUS_USER_ID, response).await?,
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a new subscriber")?;
+            response
+        }
+    };
+    if inserted_subscriber_id.is_some() {
+        metrics::counter!("subscriptions_created_total").increment(1);
+    }
+    Ok(response)
+}
 
-    Ok(StatusCode::OK)
+/// A language picked on the form wins over the browser's preferences, English is the last resort.
+fn subscriber_locale(requested: Option<&str>, headers: &HeaderMap) -> Locale {
+    requested
+        .and_then(Locale::parse)
+        .or_else(|| {
+            let accept_language = headers.get(ACCEPT_LANGUAGE)?.to_str().ok()?;
+            Locale::from_accept_language(accept_language)
+        })
+        .unwrap_or_default()
 }
 
+/// Returns `None` if a subscriber with the same email already exists.
 #[tracing::instrument(name = "Saving new subscriber in DB", skip(transaction, new_subscriber))]
 async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+    locale: Locale,
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v7(Timestamp::now(ContextV7::new()));
 
     let query = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+        ON CONFLICT (email) DO NOTHING
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        locale.as_str(),
     );
 
-    transaction.execute(query).await?;
+    let n_inserted_rows = transaction.execute(query).await?.rows_affected();
 
-    Ok(subscriber_id)
+    Ok((n_inserted_rows > 0).then_some(subscriber_id))
 }
 
-#[tracing::instrument(name = "Sending confirmation email", skip(email_client, subscriber_email))]
-async fn send_confirmation_email(
-    email_client: &EmailClient,
-    subscriber_email: EmailAddress,
+/// Put an existing subscriber (back) in the double opt-in flow.
+/// Their name and locale are left alone: whoever posted the form has not
+/// proven they own the address yet.
+/// Returns `false` if they are already confirmed, as there is nothing left to do.
+#[tracing::instrument(name = "Restart confirmation of existing subscriber", skip(transaction, email))]
+async fn restart_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &EmailAddress,
+) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'pending_confirmation'
+        WHERE email = $1 AND status <> 'confirmed'
+        "#,
+        email.as_ref(),
+    );
+    let n_updated_rows = transaction.execute(query).await?.rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Invalidate every confirmation link previously sent to a subscriber.
+#[tracing::instrument(name = "Delete subscription tokens", skip(transaction))]
+pub async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// The email is handed to the outbox, so it only goes out if `transaction` commits.
+#[tracing::instrument(
+    name = "Enqueue confirmation email",
+    skip(transaction, templates, subscriber_email, subscriber_name, token)
+)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    templates: &Templates,
+    subscriber_email: &EmailAddress,
+    subscriber_name: &str,
+    locale: Locale,
     base_url: String,
     token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = &format!("{base_url}/subscriptions/confirm?token={token}");
-    let plain_body = format!("Welcome to our newsletter!\nVisit {confirmation_link} to confirm your subscription.");
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription."
-    );
-    email_client
-        .send_email(subscriber_email, "Welcome!", &html_body, &plain_body)
-        .await
+    let email = templates
+        .confirmation_email(
+            locale,
+            &ConfirmationEmail {
+                subscriber_name,
+                confirmation_link,
+            },
+        )
+        .context("Failed to render the confirmation email")?;
+    enqueue_email(
+        transaction,
+        subscriber_email,
+        &email.subject,
+        &email.html_content,
+        &email.text_content,
+    )
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -152,15 +364,15 @@ async fn send_confirmation_email(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
+    expires_at: DateTime<Utc>,
 ) -> Result<(), StoreTokenError> {
-    dbg!(subscriber_id, subscription_token);
-
     let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
-        subscription_token,
-        subscriber_id
+        r#"INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, expires_at)
+        VALUES ($1, $2, $3)"#,
+        subscription_token.hash(),
+        subscriber_id,
+        expires_at
     );
 
     transaction.execute(query).await.map_err(StoreTokenError)?;
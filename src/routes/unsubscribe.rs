@@ -0,0 +1,142 @@
+use crate::domain::{Locale, SubscriptionToken};
+use crate::problem::Problem;
+use crate::routes::error_chain_fmt;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe token is malformed.")]
+    InvalidToken,
+
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for UnsubscribeError {
+    fn into_response(self) -> Response {
+        match &self {
+            UnsubscribeError::InvalidToken => Problem::new(StatusCode::BAD_REQUEST, self.to_string()),
+            UnsubscribeError::UnknownToken => Problem::new(StatusCode::UNAUTHORIZED, self.to_string()),
+            UnsubscribeError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+/// Ask for confirmation before unsubscribing: link scanners and prefetchers
+/// follow `GET` links, so this must not change any state.
+#[tracing::instrument(name = "Show unsubscribe page", skip(state, parameters))]
+pub async fn unsubscribe_form(
+    State(state): State<AppState>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<Html<String>, UnsubscribeError> {
+    let token = SubscriptionToken::parse(&parameters.token).map_err(|_| UnsubscribeError::InvalidToken)?;
+    let subscriber = get_subscriber_from_unsubscribe_token(&state.db, &token)
+        .await?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let page = state
+        .templates
+        .unsubscribe_page(subscriber.locale(), token.as_ref())
+        .context("Failed to render the unsubscribe page")?;
+    Ok(Html(page))
+}
+
+/// Handles both our own confirmation form and RFC 8058 one-click requests,
+/// which mail clients send as `POST <List-Unsubscribe URL>` with a
+/// `List-Unsubscribe=One-Click` body.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(state, parameters))]
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<Html<String>, UnsubscribeError> {
+    let token = SubscriptionToken::parse(&parameters.token).map_err(|_| UnsubscribeError::InvalidToken)?;
+    let subscriber = get_subscriber_from_unsubscribe_token(&state.db, &token)
+        .await?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let was_subscribed = mark_subscriber_as_unsubscribed(&state.db, subscriber.id)
+        .await
+        .context("Failed to mark the subscriber as unsubscribed")?;
+    if was_subscribed {
+        metrics::counter!("subscriptions_unsubscribed_total").increment(1);
+    }
+
+    let page = state
+        .templates
+        .unsubscribed_page(subscriber.locale())
+        .context("Failed to render the unsubscribed page")?;
+    Ok(Html(page))
+}
+
+struct Subscriber {
+    id: Uuid,
+    locale: String,
+}
+
+impl Subscriber {
+    fn locale(&self) -> Locale {
+        Locale::parse(&self.locale).unwrap_or_default()
+    }
+}
+
+#[tracing::instrument(name = "Get subscriber from unsubscribe token", skip(pool, unsubscribe_token))]
+async fn get_subscriber_from_unsubscribe_token(
+    pool: &PgPool,
+    unsubscribe_token: &SubscriptionToken,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, locale FROM subscriptions
+        JOIN unsubscribe_tokens ON unsubscribe_tokens.subscriber_id = subscriptions.id
+        WHERE unsubscribe_token_hash = $1
+        "#,
+        unsubscribe_token.hash(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber associated with the unsubscribe token")
+}
+
+/// Also invalidates the confirmation links of a pending subscription, so that
+/// an old confirmation email cannot subscribe them again.
+/// Returns `false` if they had already unsubscribed.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+async fn mark_subscriber_as_unsubscribed(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1 AND status <> 'unsubscribed'"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND status <> 'confirmed'"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,51 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use tower_sessions::Session;
+use tower_sessions::session;
+use uuid::Uuid;
+
+/// A strongly-typed wrapper around `tower_sessions::Session`, so that the
+/// rest of the application never deals with raw session keys.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const FLASH_KEY: &'static str = "flash";
+
+    /// Rotate the session id, to prevent session fixation on login.
+    pub async fn renew(&self) -> Result<(), session::Error> {
+        self.0.cycle_id().await
+    }
+
+    pub async fn insert_user_id(&self, user_id: Uuid) -> Result<(), session::Error> {
+        self.0.insert(Self::USER_ID_KEY, user_id).await
+    }
+
+    pub async fn get_user_id(&self) -> Result<Option<Uuid>, session::Error> {
+        self.0.get(Self::USER_ID_KEY).await
+    }
+
+    pub async fn log_out(&self) -> Result<(), session::Error> {
+        self.0.flush().await
+    }
+
+    /// Store a one-off message to be displayed by the next page that reads it.
+    pub async fn insert_flash(&self, message: &str) -> Result<(), session::Error> {
+        self.0.insert(Self::FLASH_KEY, message).await
+    }
+
+    pub async fn take_flash(&self) -> Result<Option<String>, session::Error> {
+        self.0.remove(Self::FLASH_KEY).await
+    }
+}
+
+impl<S> FromRequestParts<S> for TypedSession
+where
+    S: Send + Sync,
+{
+    type Rejection = <Session as FromRequestParts<S>>::Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Session::from_request_parts(parts, state).await.map(Self)
+    }
+}
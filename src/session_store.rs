@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tower_sessions::cookie::time::OffsetDateTime;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store::{self, ExpiredDeletion, SessionStore};
+
+/// A `tower-sessions` store persisting session records in the `sessions` table.
+#[derive(Clone, Debug)]
+pub struct PgSessionStore {
+    pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Periodically purge expired sessions. Runs until the process stops.
+    pub async fn delete_expired_until_stopped(self, period: std::time::Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.delete_expired().await {
+                tracing::error!(error.cause_chain = ?e, "Failed to delete expired sessions");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PgSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let data = encode(record)?;
+        loop {
+            let result = sqlx::query!(
+                r#"
+                INSERT INTO sessions (id, data, expiry_date)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+                record.id.to_string(),
+                data,
+                to_chrono(record.expiry_date),
+            )
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+            if result.rows_affected() == 1 {
+                return Ok(());
+            }
+            // Session ID collision: pick another one.
+            record.id = Id::default();
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (id, data, expiry_date)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (id) DO UPDATE
+            SET data = EXCLUDED.data, expiry_date = EXCLUDED.expiry_date
+            "#,
+            record.id.to_string(),
+            encode(record)?,
+            to_chrono(record.expiry_date),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query!(
+            r#"SELECT data, expiry_date FROM sessions WHERE id = $1 AND expiry_date > now()"#,
+            session_id.to_string(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = serde_json::from_slice(&row.data).map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        let expiry_date = OffsetDateTime::from_unix_timestamp(row.expiry_date.timestamp())
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        Ok(Some(Record {
+            id: *session_id,
+            data,
+            expiry_date,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query!(r#"DELETE FROM sessions WHERE id = $1"#, session_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for PgSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        sqlx::query!(r#"DELETE FROM sessions WHERE expiry_date <= now()"#)
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+fn encode(record: &Record) -> session_store::Result<Vec<u8>> {
+    serde_json::to_vec(&record.data).map_err(|e| session_store::Error::Encode(e.to_string()))
+}
+
+fn to_chrono(expiry_date: OffsetDateTime) -> DateTime<Utc> {
+    DateTime::from_timestamp(expiry_date.unix_timestamp(), expiry_date.nanosecond()).unwrap_or_default()
+}
+
+fn backend_error(e: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
@@ -1,81 +1,187 @@
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
-use crate::routes::{confirm, health_check, subscribe};
-use crate::telemetry::MakeSpanWithRequestId;
-use axum::Router;
+use crate::authentication::{Credentials, create_initial_admin, reject_anonymous_users};
+use crate::configuration::{DatabaseSettings, Settings, SubscriptionTokenSettings};
+use crate::metrics::{prometheus_handle, render_metrics, run_upkeep_until_stopped, track_requests};
+use crate::openapi::{ApiDoc, openapi_json};
+use crate::problem::render_errors_as_problems;
+use crate::routes::{
+    admin_dashboard, confirm, health_check, log_out, login, login_form, preview_newsletter, publish_newsletter,
+    resend_confirmation, subscribe, unsubscribe, unsubscribe_form,
+};
+use crate::session_store::PgSessionStore;
+use crate::telemetry::{MakeSpanWithRequestId, assign_request_id};
+use crate::templates::Templates;
 use axum::routing::{get, post};
 use axum::serve::Serve;
+use axum::{Router, middleware};
 use reqwest::Url;
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
+use std::future::IntoFuture;
 use std::sync::Arc;
 use tokio::net::{TcpListener as TokioTcpListener, TcpListener};
 use tower_http::trace::TraceLayer;
+use tower_sessions::cookie::time::Duration;
+use tower_sessions::{Expiry, SessionManagerLayer};
+use utoipa::OpenApi;
+use utoipa_scalar::{Scalar, Servable};
 
 type AppServer = Serve<TcpListener, Router, Router>;
 
 pub struct Application {
     port: u16,
     server: AppServer,
+    metrics_port: Option<u16>,
+    metrics_server: Option<AppServer>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<PgPool>,
-    pub email_client: Arc<EmailClient>,
     pub base_url: String,
+    pub subscription_tokens: SubscriptionTokenSettings,
+    pub templates: Arc<Templates>,
+    pub confirmation_redirect_url: Option<Url>,
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Application, std::io::Error> {
         let connection_pool = Application::get_connection_pool(&configuration.database);
+        let templates = Templates::load(&configuration.templates.directory).map_err(std::io::Error::other)?;
+        let confirmation_redirect_url = configuration
+            .application
+            .confirmation_redirect_url
+            .as_deref()
+            .map(Url::parse)
+            .transpose()
+            .map_err(std::io::Error::other)?;
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address");
-
-        let email_provider_url = Url::parse(configuration.email_client.base_url.as_str()).expect("Failed to parse URL");
-        let email_client_timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            email_provider_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            email_client_timeout,
-        );
+        if let Some(admin) = configuration.initial_admin {
+            let credentials = Credentials {
+                username: admin.username,
+                password: admin.password,
+            };
+            if create_initial_admin(credentials, &connection_pool)
+                .await
+                .map_err(std::io::Error::other)?
+            {
+                tracing::info!("Created the initial admin");
+            }
+        }
 
         let address = format!("{}:{}", configuration.application.host, configuration.application.port);
         let listener = TcpListener::bind(address).await?;
         let port = listener.local_addr()?.port();
 
+        tokio::spawn(run_upkeep_until_stopped(
+            prometheus_handle(),
+            std::time::Duration::from_secs(5),
+        ));
+
+        let session_store = PgSessionStore::new(connection_pool.clone());
+        tokio::spawn(
+            session_store
+                .clone()
+                .delete_expired_until_stopped(std::time::Duration::from_secs(60)),
+        );
+        // Only mark the session cookie as `Secure` when we are actually served over HTTPS,
+        // otherwise browsers (and our test client) would never send it back.
+        let session_layer = SessionManagerLayer::new(session_store)
+            .with_secure(configuration.application.base_url.starts_with("https://"))
+            .with_expiry(Expiry::OnInactivity(Duration::hours(1)));
+
         let state = AppState {
             db: Arc::new(connection_pool),
-            email_client: Arc::new(email_client),
             base_url: configuration.application.base_url,
+            subscription_tokens: configuration.subscription_tokens,
+            templates: Arc::new(templates),
+            confirmation_redirect_url,
+        };
+        let (metrics_port, metrics_server) = match configuration.application.metrics_port {
+            Some(metrics_port) => {
+                let address = format!("{}:{}", configuration.application.metrics_host, metrics_port);
+                let listener = TcpListener::bind(address).await?;
+                let metrics_port = listener.local_addr()?.port();
+                let metrics_routes = Router::new()
+                    .route("/metrics", get(render_metrics))
+                    .with_state(state.clone());
+                (Some(metrics_port), Some(axum::serve(listener, metrics_routes)))
+            }
+            None => (None, None),
         };
-        let server = Application::run(listener, state);
+        let server = Application::run(
+            listener,
+            state,
+            session_layer,
+            configuration.application.api_docs_ui,
+            metrics_server.is_none(),
+        );
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics_port,
+            metrics_server,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The port `/metrics` is served on, if it is not the public one.
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        match self.metrics_server {
+            Some(metrics_server) => {
+                tokio::try_join!(self.server.into_future(), metrics_server.into_future())?;
+                Ok(())
+            }
+            None => self.server.await,
+        }
     }
 
-    fn run(listener: TokioTcpListener, state: AppState) -> AppServer {
-        let app = Router::new()
+    fn run(
+        listener: TokioTcpListener,
+        state: AppState,
+        session_layer: SessionManagerLayer<PgSessionStore>,
+        api_docs_ui: bool,
+        serve_metrics: bool,
+    ) -> AppServer {
+        let admin_routes = Router::new()
+            .route("/dashboard", get(admin_dashboard))
+            .route("/logout", post(log_out))
+            .route("/newsletters/preview", post(preview_newsletter))
+            .layer(middleware::from_fn(reject_anonymous_users));
+
+        let mut app = Router::new()
             .route("/health-check", get(health_check))
+            .route("/openapi.json", get(openapi_json))
             .route("/subscriptions/confirm", get(confirm))
             .route("/subscriptions", post(subscribe))
+            .route("/subscriptions/resend-confirmation", post(resend_confirmation))
+            .route("/subscriptions/unsubscribe", get(unsubscribe_form).post(unsubscribe))
+            .route("/newsletters", post(publish_newsletter))
+            .route("/login", get(login_form).post(login))
+            .nest("/admin", admin_routes);
+        if api_docs_ui {
+            app = app.merge(Scalar::with_url("/docs", ApiDoc::openapi()));
+        }
+        if serve_metrics {
+            app = app.route("/metrics", get(render_metrics));
+        }
+        let app = app
+            .route_layer(middleware::from_fn(track_requests))
+            .layer(middleware::from_fn(render_errors_as_problems))
+            .layer(session_layer)
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(MakeSpanWithRequestId)
                     .on_failure(()),
             )
+            .layer(middleware::from_fn(assign_request_id))
             .with_state(state);
 
         axum::serve(listener, app)
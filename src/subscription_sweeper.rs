@@ -0,0 +1,82 @@
+use crate::configuration::Settings;
+use crate::idempotency::IDEMPOTENCY_KEY_TTL;
+use crate::startup::Application;
+use sqlx::postgres::types::PgInterval;
+use sqlx::{Executor, PgPool};
+use std::time::Duration;
+
+pub async fn run_sweeper_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = Application::get_connection_pool(&configuration.database);
+    sweeper_loop(connection_pool, configuration.subscription_tokens.sweep_interval()).await
+}
+
+async fn sweeper_loop(pool: PgPool, period: Duration) -> Result<(), anyhow::Error> {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        // Errors are logged by `sweep_expired_subscriptions`, we just try again on the next tick.
+        let _ = sweep_expired_subscriptions(&pool).await;
+    }
+}
+
+/// Delete expired unconfirmed tokens, then the pending subscriptions that
+/// are left without any token to confirm them with nor a new one on the way, as well as stale
+/// confirmation resend records, expired idempotency keys, unsubscribe tokens
+/// no longer needed and the outbox emails sent a week ago.
+#[tracing::instrument(name = "Sweep expired subscriptions", skip(pool), err)]
+pub async fn sweep_expired_subscriptions(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // Confirmed tokens are kept, so that following a confirmation link again still says so.
+    let query = sqlx::query!(r#"DELETE FROM subscription_tokens WHERE status <> 'confirmed' AND expires_at <= now()"#);
+    let n_deleted_tokens = transaction.execute(query).await?.rows_affected();
+
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE
+            status = 'pending_confirmation' AND
+            NOT EXISTS (SELECT 1 FROM subscription_tokens WHERE subscriber_id = subscriptions.id) AND
+            NOT EXISTS (
+                SELECT 1 FROM confirmation_resends
+                WHERE email = subscriptions.email AND processed_at IS NULL
+            )
+        "#
+    );
+    let n_deleted_subscriptions = transaction.execute(query).await?.rows_affected();
+
+    // Only the last hour matters for rate limiting confirmation resends.
+    let query = sqlx::query!(r#"DELETE FROM confirmation_resends WHERE requested_at <= now() - interval '1 hour'"#);
+    transaction.execute(query).await?;
+
+    let query = sqlx::query!(
+        r#"DELETE FROM idempotency WHERE created_at <= now() - $1::interval"#,
+        PgInterval::try_from(IDEMPOTENCY_KEY_TTL).expect("The idempotency key TTL fits in an interval"),
+    );
+    transaction.execute(query).await?;
+
+    // Links in the emails of subscribers who left have served their purpose, and
+    // those in year-old issues are not worth keeping a row per email around for.
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM unsubscribe_tokens
+        WHERE
+            created_at <= now() - interval '1 year' OR
+            subscriber_id IN (SELECT id FROM subscriptions WHERE status = 'unsubscribed')
+        "#
+    );
+    transaction.execute(query).await?;
+
+    // Sent emails are only kept around for a while to investigate delivery issues.
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_outbox
+        WHERE coalesce(delivered_at, failed_at) <= now() - interval '7 days'
+        "#
+    );
+    transaction.execute(query).await?;
+
+    transaction.commit().await?;
+    tracing::info!(n_deleted_tokens, n_deleted_subscriptions, "Swept expired subscriptions");
+    Ok(())
+}
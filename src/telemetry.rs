@@ -1,5 +1,7 @@
-use axum::extract::MatchedPath;
-use axum::http::Request;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use tokio::task::JoinHandle;
 use tower_http::trace::MakeSpan;
 use tracing::subscriber::set_global_default;
 use tracing::{Span, Subscriber, info_span};
@@ -23,17 +25,69 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     set_global_default(subscriber).expect("Failed to set subscriber")
 }
 
+/// Run CPU-heavy work (e.g. password hashing) on the blocking thread pool
+/// without losing the caller's tracing context.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+/// Identifies a request in the logs and in the error responses sent back for it.
+#[derive(Copy, Clone, Debug)]
+pub struct RequestId(pub Uuid);
+
+/// The request being handled, for responses built away from the request itself.
+#[derive(Clone, Debug)]
+pub struct CurrentRequest {
+    pub id: RequestId,
+    /// Without the query string, which may carry tokens.
+    pub path: String,
+}
+
+tokio::task_local! {
+    static CURRENT_REQUEST: CurrentRequest;
+}
+
+impl CurrentRequest {
+    /// `None` outside of a request, e.g. in background workers.
+    pub fn get() -> Option<CurrentRequest> {
+        CURRENT_REQUEST.try_with(Clone::clone).ok()
+    }
+}
+
+/// Must wrap the `TraceLayer`, so that `MakeSpanWithRequestId` finds the id.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let current_request = CurrentRequest {
+        id: RequestId(Uuid::new_v4()),
+        path: request.uri().path().to_owned(),
+    };
+    request.extensions_mut().insert(current_request.id);
+    CURRENT_REQUEST.scope(current_request, next.run(request)).await
+}
+
 #[derive(Clone)]
 pub struct MakeSpanWithRequestId;
 
 impl<Body> MakeSpan<Body> for MakeSpanWithRequestId {
-    fn make_span(&mut self, request: &Request<Body>) -> Span {
+    fn make_span(&mut self, request: &axum::http::Request<Body>) -> Span {
         let matched_path = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map_or_else(Uuid::new_v4, |id| id.0);
+        // Authentication happens in extractors and middlewares, outside of the
+        // handler's span: the admin is recorded on the request span instead.
         info_span!(
             "http_request",
             method = ?request.method(),
             matched_path,
-            request_id = Uuid::new_v4().to_string(),
+            request_id = request_id.to_string(),
+            username = tracing::field::Empty,
+            user_id = tracing::field::Empty,
         )
     }
 }
@@ -0,0 +1,473 @@
+use crate::domain::{ConfirmationOutcome, Locale};
+use crate::routes::error_chain_fmt;
+use fluent::concurrent::FluentBundle;
+use fluent::{FluentArgs, FluentResource, FluentValue};
+use minijinja::value::{Kwargs, ValueKind};
+use minijinja::{AutoEscape, Environment, Error, ErrorKind, Output, State, UndefinedBehavior, Value, context};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Every email is made of a subject, an HTML body and a plain text body,
+/// found under `emails/<name>/` in the templates directory.
+const CONFIRMATION_EMAIL: &str = "confirmation";
+const NEWSLETTER_EMAIL: &str = "newsletter";
+
+/// One Fluent catalog per locale, `translations/<locale>.ftl`.
+const TRANSLATIONS_DIRECTORY: &str = "translations";
+
+/// The templates in the `templates/` directory, parsed once at startup.
+/// Values rendered in `.html` templates are HTML-escaped unless marked `|safe`.
+/// Copy is looked up with `t("message-id", arg=value)` in the catalog of the
+/// `locale` being rendered, falling back to English for missing messages.
+#[derive(Debug)]
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConfirmationEmail<'a> {
+    pub subscriber_name: &'a str,
+    pub confirmation_link: &'a str,
+}
+
+#[derive(serde::Serialize)]
+pub struct NewsletterEmail<'a> {
+    pub title: &'a str,
+    /// Written by the editors, so it is rendered as is.
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_link: &'a str,
+}
+
+#[derive(thiserror::Error)]
+pub enum TemplateError {
+    #[error("Failed to read the templates in {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    #[error("Invalid translation catalog {0}: {1}")]
+    InvalidCatalog(PathBuf, String),
+
+    #[error(transparent)]
+    Invalid(#[from] Error),
+}
+
+impl std::fmt::Debug for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl Templates {
+    /// Parse every template and catalog in `directory` and render each email
+    /// and page once per locale with sample values, so that a broken template
+    /// stops the application from starting rather than failing on the first send.
+    #[tracing::instrument(name = "Load templates", skip_all, fields(directory = %directory.as_ref().display()))]
+    pub fn load(directory: impl AsRef<Path>) -> Result<Self, TemplateError> {
+        let directory = directory.as_ref();
+        let catalogs = Arc::new(load_catalogs(&directory.join(TRANSLATIONS_DIRECTORY))?);
+
+        let mut env = Environment::new();
+        // A typo in a variable name must be an error, not an empty string.
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_formatter(escape_formatter);
+        env.add_function("t", move |state: &State, id: &str, args: Kwargs| {
+            translate(&catalogs, state, id, args)
+        });
+        add_templates(&mut env, directory, directory)?;
+
+        let templates = Self { env };
+        for locale in Locale::ALL {
+            templates.confirmation_email(
+                locale,
+                &ConfirmationEmail {
+                    subscriber_name: "Ursula Le Guin",
+                    confirmation_link: "https://example.com/subscriptions/confirm?token=token",
+                },
+            )?;
+            templates.newsletter_email(
+                locale,
+                &NewsletterEmail {
+                    title: "Newsletter title",
+                    html_content: "<p>Newsletter body as HTML</p>",
+                    text_content: "Newsletter body as plain text",
+                    unsubscribe_link: "https://example.com/subscriptions/unsubscribe?token=token",
+                },
+            )?;
+            for outcome in ConfirmationOutcome::ALL {
+                templates.confirmation_page(locale, outcome)?;
+            }
+            templates.unsubscribe_page(locale, "token")?;
+            templates.unsubscribed_page(locale)?;
+            templates.admin_dashboard_page(locale, "admin")?;
+            templates.login_page(locale, Some("Authentication failed."))?;
+        }
+        Ok(templates)
+    }
+
+    pub fn confirmation_email(&self, locale: Locale, context: &ConfirmationEmail) -> Result<RenderedEmail, Error> {
+        self.render_email(CONFIRMATION_EMAIL, locale, Value::from_serialize(context))
+    }
+
+    pub fn newsletter_email(&self, locale: Locale, context: &NewsletterEmail) -> Result<RenderedEmail, Error> {
+        self.render_email(NEWSLETTER_EMAIL, locale, Value::from_serialize(context))
+    }
+
+    pub fn confirmation_page(&self, locale: Locale, outcome: ConfirmationOutcome) -> Result<String, Error> {
+        self.render_page("confirmation.html", locale, context! { outcome })
+    }
+
+    pub fn unsubscribe_page(&self, locale: Locale, token: &str) -> Result<String, Error> {
+        self.render_page("unsubscribe.html", locale, context! { token })
+    }
+
+    pub fn unsubscribed_page(&self, locale: Locale) -> Result<String, Error> {
+        self.render_page("unsubscribed.html", locale, context! {})
+    }
+
+    pub fn admin_dashboard_page(&self, locale: Locale, username: &str) -> Result<String, Error> {
+        self.render_page("admin_dashboard.html", locale, context! { username })
+    }
+
+    pub fn login_page(&self, locale: Locale, flash_message: Option<&str>) -> Result<String, Error> {
+        self.render_page("login.html", locale, context! { flash_message })
+    }
+
+    fn render_email(&self, name: &str, locale: Locale, context: Value) -> Result<RenderedEmail, Error> {
+        let context = context! { locale => locale.as_str(), ..context };
+        let render = |part: &str| {
+            self.env
+                .get_template(&format!("emails/{name}/{part}"))?
+                .render(&context)
+        };
+        Ok(RenderedEmail {
+            subject: render("subject.txt")?.trim().to_owned(),
+            html_content: render("body.html")?,
+            text_content: render("body.txt")?,
+        })
+    }
+
+    fn render_page(&self, name: &str, locale: Locale, context: Value) -> Result<String, Error> {
+        self.env
+            .get_template(&format!("pages/{name}"))?
+            .render(context! { locale => locale.as_str(), ..context })
+    }
+}
+
+type Catalogs = HashMap<Locale, FluentBundle<FluentResource>>;
+
+fn load_catalogs(directory: &Path) -> Result<Catalogs, TemplateError> {
+    let mut catalogs = HashMap::new();
+    let mut default_ids = vec![];
+    for locale in Locale::ALL {
+        let path = directory.join(format!("{}.ftl", locale.as_str()));
+        let source = std::fs::read_to_string(&path).map_err(|e| TemplateError::Io(path.clone(), e))?;
+        let resource = FluentResource::try_new(source)
+            .map_err(|(_, errors)| TemplateError::InvalidCatalog(path.clone(), format!("{errors:?}")))?;
+        if locale == Locale::default() {
+            default_ids = message_ids(&resource);
+        }
+        let language = locale.as_str().parse().expect("Locales are valid language identifiers");
+        let mut bundle = FluentBundle::new_concurrent(vec![language]);
+        // Unicode isolation marks around arguments would end up in links and plain text emails.
+        bundle.set_use_isolating(false);
+        bundle
+            .add_resource(resource)
+            .map_err(|errors| TemplateError::InvalidCatalog(path.clone(), format!("{errors:?}")))?;
+        catalogs.insert(locale, bundle);
+    }
+
+    // Missing translations fall back to the default locale, so they are not fatal.
+    for (locale, bundle) in &catalogs {
+        for id in default_ids.iter().filter(|id| !bundle.has_message(id)) {
+            tracing::warn!("The `{}` catalog has no `{id}` message", locale.as_str());
+        }
+    }
+    Ok(catalogs)
+}
+
+fn message_ids(resource: &FluentResource) -> Vec<String> {
+    resource
+        .entries()
+        .filter_map(|entry| match entry {
+            fluent_syntax::ast::Entry::Message(message) => Some(message.id.name.to_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn translate(catalogs: &Catalogs, state: &State, id: &str, args: Kwargs) -> Result<String, Error> {
+    let locale = state
+        .lookup("locale")
+        .and_then(|l| l.as_str().and_then(Locale::parse))
+        .ok_or_else(|| Error::new(ErrorKind::UndefinedError, "`t` needs a `locale` to translate into"))?;
+    let (bundle, pattern) = [locale, Locale::default()]
+        .into_iter()
+        .map(|locale| &catalogs[&locale])
+        .find_map(|bundle| Some((bundle, bundle.get_message(id)?.value()?)))
+        .ok_or_else(|| Error::new(ErrorKind::UndefinedError, format!("No `{id}` message to translate")))?;
+
+    let mut fluent_args = FluentArgs::new();
+    for name in args.args() {
+        let value: Value = args.get(name)?;
+        let value = match value.kind() {
+            ValueKind::Number => FluentValue::from(f64::try_from(value)?),
+            _ => FluentValue::from(value.to_string()),
+        };
+        fluent_args.set(name.to_owned(), value);
+    }
+    let mut errors = vec![];
+    let translation = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+    if !errors.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Failed to translate `{id}`: {errors:?}"),
+        ));
+    }
+    Ok(translation.into_owned())
+}
+
+/// Templates are named after their path relative to `root`, e.g. `emails/newsletter/body.html`.
+fn add_templates(env: &mut Environment<'static>, root: &Path, directory: &Path) -> Result<(), TemplateError> {
+    let io_error = |e| TemplateError::Io(directory.to_owned(), e);
+    for entry in std::fs::read_dir(directory).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path == root.join(TRANSLATIONS_DIRECTORY) {
+            continue;
+        }
+        if path.is_dir() {
+            add_templates(env, root, &path)?;
+            continue;
+        }
+        let name = path
+            .strip_prefix(root)
+            .expect("Templates are always found under the root directory")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let source = std::fs::read_to_string(&path).map_err(io_error)?;
+        env.add_template_owned(name, source)?;
+    }
+    Ok(())
+}
+
+/// Same as the default formatter, but leaves `/` alone when escaping HTML:
+/// escaping it is not needed for safety and mangles the links we render.
+fn escape_formatter(out: &mut Output, state: &State, value: &Value) -> Result<(), Error> {
+    if state.auto_escape() != AutoEscape::Html || value.is_safe() || value.is_none() || value.is_undefined() {
+        return minijinja::escape_formatter(out, state, value);
+    }
+    let value = value.to_string();
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    out.write_str(&escaped).map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfirmationEmail, NewsletterEmail, Templates};
+    use crate::domain::{ConfirmationOutcome, Locale};
+    use claims::assert_err;
+    use std::path::{Path, PathBuf};
+
+    fn templates() -> Templates {
+        Templates::load("templates").expect("Failed to load the templates")
+    }
+
+    fn confirmation_email(subscriber_name: &str) -> ConfirmationEmail<'_> {
+        ConfirmationEmail {
+            subscriber_name,
+            confirmation_link: "http://127.0.0.1/subscriptions/confirm?token=abc123",
+        }
+    }
+
+    /// A copy of the real templates, for tests that need to break them.
+    fn copy_of_templates() -> PathBuf {
+        fn copy(from: &Path, to: &Path) {
+            std::fs::create_dir_all(to).unwrap();
+            for entry in std::fs::read_dir(from).unwrap() {
+                let path = entry.unwrap().path();
+                let target = to.join(path.file_name().unwrap());
+                if path.is_dir() {
+                    copy(&path, &target);
+                } else {
+                    std::fs::copy(&path, &target).unwrap();
+                }
+            }
+        }
+        let directory = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        copy(Path::new("templates"), &directory);
+        directory
+    }
+
+    #[test]
+    fn confirmation_email_snapshot() {
+        let email = templates()
+            .confirmation_email(Locale::En, &confirmation_email("Ursula"))
+            .unwrap();
+
+        assert_eq!(email.subject, "Welcome!");
+        insta::assert_snapshot!("confirmation_email_html", email.html_content);
+        insta::assert_snapshot!("confirmation_email_text", email.text_content);
+    }
+
+    #[test]
+    fn spanish_confirmation_email_snapshot() {
+        let email = templates()
+            .confirmation_email(Locale::Es, &confirmation_email("Ursula"))
+            .unwrap();
+
+        insta::assert_snapshot!("spanish_confirmation_email_subject", email.subject);
+        insta::assert_snapshot!("spanish_confirmation_email_html", email.html_content);
+        insta::assert_snapshot!("spanish_confirmation_email_text", email.text_content);
+    }
+
+    #[test]
+    fn newsletter_email_snapshot() {
+        let email = templates()
+            .newsletter_email(
+                Locale::En,
+                &NewsletterEmail {
+                    title: "Issue #1",
+                    html_content: "<p>Newsletter <em>body</em></p>",
+                    text_content: "Newsletter body",
+                    unsubscribe_link: "http://127.0.0.1/subscriptions/unsubscribe?token=abc123",
+                },
+            )
+            .unwrap();
+
+        assert_eq!(email.subject, "Issue #1");
+        insta::assert_snapshot!("newsletter_email_html", email.html_content);
+        insta::assert_snapshot!("newsletter_email_text", email.text_content);
+    }
+
+    #[test]
+    fn spanish_confirmation_page_snapshot() {
+        let page = templates()
+            .confirmation_page(Locale::Es, ConfirmationOutcome::Confirmed)
+            .unwrap();
+
+        insta::assert_snapshot!("spanish_confirmation_page", page);
+    }
+
+    #[test]
+    fn expired_confirmation_page_snapshot() {
+        let page = templates()
+            .confirmation_page(Locale::En, ConfirmationOutcome::Expired)
+            .unwrap();
+
+        insta::assert_snapshot!("expired_confirmation_page", page);
+    }
+
+    #[test]
+    fn invalid_confirmation_page_snapshot() {
+        let page = templates()
+            .confirmation_page(Locale::En, ConfirmationOutcome::Invalid)
+            .unwrap();
+
+        insta::assert_snapshot!("invalid_confirmation_page", page);
+    }
+
+    #[test]
+    fn subscriber_names_cannot_inject_markup_in_html_emails() {
+        let name = r#"<script>alert("pwned")</script> & 'friends'"#;
+
+        let email = templates()
+            .confirmation_email(Locale::En, &confirmation_email(name))
+            .unwrap();
+
+        assert!(!email.html_content.contains("<script>"));
+        assert!(
+            email
+                .html_content
+                .contains("&lt;script&gt;alert(&quot;pwned&quot;)&lt;/script&gt; &amp; &#x27;friends&#x27;")
+        );
+        // Plain text bodies are not HTML, they must not be escaped.
+        assert!(email.text_content.contains(name));
+    }
+
+    #[test]
+    fn usernames_cannot_inject_markup_in_the_admin_dashboard() {
+        let page = templates()
+            .admin_dashboard_page(Locale::En, r#"<script>alert("pwned")</script>"#)
+            .unwrap();
+
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("Welcome &lt;script&gt;alert(&quot;pwned&quot;)&lt;/script&gt;!"));
+    }
+
+    #[test]
+    fn flash_messages_cannot_inject_markup_in_the_login_page() {
+        let page = templates()
+            .login_page(Locale::En, Some("<script>alert(1)</script>"))
+            .unwrap();
+
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("<p><i>&lt;script&gt;alert(1)&lt;/script&gt;</i></p>"));
+    }
+
+    #[test]
+    fn a_template_using_an_unknown_variable_is_rejected_at_load_time() {
+        let directory = copy_of_templates();
+        std::fs::write(
+            directory.join("emails/confirmation/body.html"),
+            "Hi {{ subscriber_nmae }}",
+        )
+        .unwrap();
+
+        assert_err!(Templates::load(&directory));
+    }
+
+    #[test]
+    fn a_template_using_an_unknown_message_is_rejected_at_load_time() {
+        let directory = copy_of_templates();
+        std::fs::write(
+            directory.join("emails/confirmation/subject.txt"),
+            r#"{{ t("no-such-message") }}"#,
+        )
+        .unwrap();
+
+        assert_err!(Templates::load(&directory));
+    }
+
+    #[test]
+    fn messages_missing_from_a_catalog_fall_back_to_english() {
+        let directory = copy_of_templates();
+        let catalog = directory.join("translations/es.ftl");
+        let spanish = std::fs::read_to_string(&catalog).unwrap();
+        let spanish: Vec<_> = spanish
+            .lines()
+            .filter(|l| !l.starts_with("confirmation-email-subject"))
+            .collect();
+        std::fs::write(&catalog, spanish.join("\n")).unwrap();
+
+        let email = Templates::load(&directory)
+            .unwrap()
+            .confirmation_email(Locale::Es, &confirmation_email("Ursula"))
+            .unwrap();
+
+        assert_eq!(email.subject, "Welcome!");
+    }
+
+    #[test]
+    fn a_missing_templates_directory_is_rejected() {
+        assert_err!(Templates::load("does-not-exist"));
+    }
+}
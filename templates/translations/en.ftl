@@ -0,0 +1,35 @@
+## Confirmation email
+
+confirmation-email-subject = Welcome!
+confirmation-email-greeting = Hi { $name },
+confirmation-email-welcome = Welcome to our newsletter!
+confirmation-email-link = Click here to confirm your subscription.
+confirmation-email-instructions = Visit { $link } to confirm your subscription.
+
+## Newsletter issues
+
+newsletter-email-footer = You are receiving this email because you subscribed to our newsletter.
+newsletter-email-unsubscribe = Unsubscribe
+
+## Pages
+
+confirmation-page-confirmed-title = Subscription confirmed
+confirmation-page-confirmed-message = Thanks for confirming your subscription! You will receive our next issue.
+confirmation-page-expired-title = Link expired
+confirmation-page-expired-message = This confirmation link has expired. Subscribe again to get a new one.
+confirmation-page-invalid-title = Invalid link
+confirmation-page-invalid-message = This confirmation link is not valid. Make sure you copied the whole link from the email, or subscribe again.
+unsubscribe-page-title = Unsubscribe
+unsubscribe-page-question = Do you want to stop receiving our newsletter?
+unsubscribe-page-button = Unsubscribe
+unsubscribed-page-title = Unsubscribed
+unsubscribed-page-message = You have been unsubscribed. You will not receive any further issues.
+admin-dashboard-title = Admin dashboard
+admin-dashboard-welcome = Welcome { $username }!
+admin-dashboard-logout = Logout
+login-page-title = Login
+login-page-username = Username
+login-page-username-placeholder = Enter Username
+login-page-password = Password
+login-page-password-placeholder = Enter Password
+login-page-button = Login
@@ -0,0 +1,35 @@
+## Confirmation email
+
+confirmation-email-subject = ¡Te damos la bienvenida!
+confirmation-email-greeting = Hola, { $name }:
+confirmation-email-welcome = ¡Te damos la bienvenida a nuestro boletín!
+confirmation-email-link = Haz clic aquí para confirmar tu suscripción.
+confirmation-email-instructions = Visita { $link } para confirmar tu suscripción.
+
+## Newsletter issues
+
+newsletter-email-footer = Recibes este correo porque te suscribiste a nuestro boletín.
+newsletter-email-unsubscribe = Cancelar la suscripción
+
+## Pages
+
+confirmation-page-confirmed-title = Suscripción confirmada
+confirmation-page-confirmed-message = ¡Gracias por confirmar tu suscripción! Recibirás nuestro próximo número.
+confirmation-page-expired-title = Enlace caducado
+confirmation-page-expired-message = Este enlace de confirmación ha caducado. Vuelve a suscribirte para recibir uno nuevo.
+confirmation-page-invalid-title = Enlace no válido
+confirmation-page-invalid-message = Este enlace de confirmación no es válido. Asegúrate de haber copiado el enlace completo del correo o vuelve a suscribirte.
+unsubscribe-page-title = Cancelar la suscripción
+unsubscribe-page-question = ¿Quieres dejar de recibir nuestro boletín?
+unsubscribe-page-button = Cancelar la suscripción
+unsubscribed-page-title = Suscripción cancelada
+unsubscribed-page-message = Has cancelado tu suscripción. No recibirás más números.
+admin-dashboard-title = Panel de administración
+admin-dashboard-welcome = ¡Bienvenido, { $username }!
+admin-dashboard-logout = Cerrar sesión
+login-page-title = Iniciar sesión
+login-page-username = Usuario
+login-page-username-placeholder = Introduce tu usuario
+login-page-password = Contraseña
+login-page-password-placeholder = Introduce tu contraseña
+login-page-button = Iniciar sesión
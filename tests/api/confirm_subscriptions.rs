@@ -1,6 +1,7 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionToken;
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
     // Arrange
@@ -32,13 +33,14 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/email"))
+    Mock::given(path("/emails"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
 
@@ -53,12 +55,13 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     // Arrange
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    Mock::given(path("/email"))
+    Mock::given(path("/emails"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
         .await;
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
     // Act
@@ -82,12 +85,13 @@ async fn clicking_on_the_confirmation_link_updates_subscription_token_status_to_
     // Arrange
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    Mock::given(path("/email"))
+    Mock::given(path("/emails"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
         .await;
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
@@ -103,10 +107,241 @@ async fn clicking_on_the_confirmation_link_updates_subscription_token_status_to_
         .unwrap();
 
     // Assert
-    let query_record = sqlx::query!("SELECT subscription_token, status FROM subscription_tokens",)
+    let query_record = sqlx::query!("SELECT subscription_token_hash, status FROM subscription_tokens",)
         .fetch_one(&app.db_pool)
         .await
         .expect("Failed to fetch saved subscription.");
-    assert_eq!(query_record.subscription_token, token);
+    assert_eq!(
+        query_record.subscription_token_hash,
+        SubscriptionToken::parse(&token).unwrap().hash()
+    );
     assert_eq!(query_record.status, "confirmed");
 }
+
+#[tokio::test]
+async fn confirmations_with_an_expired_token_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn new_tokens_expire_after_the_configured_ttl() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    create_unconfirmed_subscriber(&app).await;
+
+    // Assert
+    let token = sqlx::query!(
+        r#"SELECT round(EXTRACT(EPOCH FROM expires_at - created_at) / 60)::INT as "ttl_minutes!" FROM subscription_tokens"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(token.ttl_minutes, 24 * 60);
+}
+
+#[tokio::test]
+async fn subscription_tokens_are_not_stored_in_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Assert
+    let token = confirmation_links.html.query_pairs().next().unwrap().1.to_string();
+    let n_plain_text_tokens = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!" FROM subscription_tokens WHERE subscription_token_hash = $1"#,
+        token
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_plain_text_tokens, 0);
+}
+
+#[tokio::test]
+async fn the_confirmation_page_is_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=es";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<html lang="es">"#));
+    assert!(page.contains("Suscripción confirmada"));
+}
+
+#[tokio::test]
+async fn browsers_following_an_expired_link_get_an_explanation() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("This confirmation link has expired.")
+    );
+}
+
+#[tokio::test]
+async fn browsers_following_an_unknown_link_get_an_explanation_in_their_language() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = SubscriptionToken::new();
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?token={}",
+            app.address,
+            token.as_ref()
+        ))
+        .header("Accept-Language", "es-ES,es;q=0.9")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.text().await.unwrap().contains("Enlace no válido"));
+}
+
+#[tokio::test]
+async fn api_clients_get_the_outcome_of_a_confirmation_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(confirmation_links.html.clone())
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, serde_json::json!({ "status": "confirmed" }));
+}
+
+#[tokio::test]
+async fn api_clients_get_failed_confirmations_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/confirm?token=abc", app.address))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], 400);
+    assert_eq!(body["detail"], "The confirmation token is malformed.");
+}
+
+#[tokio::test]
+async fn browsers_are_redirected_to_the_configured_confirmation_url() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_redirect_url = Some("https://example.com/welcome?utm_source=email".into());
+    })
+    .await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.api_client.get(confirmation_links.html).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/welcome?utm_source=email&status=confirmed"
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn expired_links_are_redirected_with_their_status() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_redirect_url = Some("https://example.com/welcome".into());
+    })
+    .await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.api_client.get(confirmation_links.html).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/welcome?status=expired"
+    );
+}
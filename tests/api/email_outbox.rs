@@ -0,0 +1,162 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+#[tokio::test]
+async fn subscribe_succeeds_even_if_the_email_provider_is_down() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email = sqlx::query!("SELECT n_retries, delivered_at, failed_at FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(email.n_retries, 1);
+    assert!(email.delivered_at.is_none());
+    assert!(email.failed_at.is_none());
+}
+
+#[tokio::test]
+async fn dispatched_emails_are_marked_as_delivered_and_sent_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email = sqlx::query!("SELECT recipient, delivered_at FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(email.recipient, "ursula_le_guin@gmail.com");
+    assert!(email.delivered_at.is_some());
+}
+
+#[tokio::test]
+async fn the_content_of_delivered_emails_is_scrubbed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email = sqlx::query!("SELECT subject, html_content, text_content FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(email.subject, "");
+    assert_eq!(email.html_content, "");
+    assert_eq!(email.text_content, "");
+}
+
+#[tokio::test]
+async fn nothing_is_enqueued_if_the_subscription_is_rolled_back() {
+    // Arrange
+    let app = spawn_app().await;
+    // Sabotage the idempotency table: saving the response, the last step before
+    // committing, fails after the email was enqueued.
+    sqlx::query!("ALTER TABLE idempotency DROP COLUMN response_body;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_subscriptions_with_idempotency_key(BODY.into(), &idempotency_key)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let n_emails = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_emails, 0);
+}
+
+#[tokio::test]
+async fn emails_are_marked_as_failed_after_too_many_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(BODY.into()).await;
+    sqlx::query!("UPDATE email_outbox SET n_retries = 4")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email = sqlx::query!("SELECT delivered_at, failed_at FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(email.delivered_at.is_none());
+    assert!(email.failed_at.is_some());
+}
+
+#[tokio::test]
+async fn emails_are_requeued_for_when_the_provider_asks_us_to_come_back() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(BODY.into()).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email = sqlx::query!(
+        r#"SELECT n_retries, execute_after > now() + interval '50 minutes' as "later!" FROM email_outbox"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(email.n_retries, 1);
+    assert!(email.later);
+}
@@ -1,11 +1,21 @@
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{DatabaseSettings, get_configuration};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::configuration::{
+    DatabaseSettings, EmailTransportKind, Settings, SubscriptionTokenSettings, get_configuration,
+};
+use zero2prod::confirmation_resender::try_resend_confirmation;
+use zero2prod::domain::SubscriptionToken;
+use zero2prod::email_client::EmailTransport;
+use zero2prod::email_outbox::try_dispatch_email;
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, try_execute_task};
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::templates::Templates;
 
 static TRACING: LazyLock<()> = LazyLock::new(|| {
     let default_filter_level = "info".to_string();
@@ -24,24 +34,263 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(SecretString::from(self.password.clone())).unwrap();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash.expose_secret(),
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
 pub struct TestApp {
     pub address: String,
     pub port: u16,
+    pub metrics_port: Option<u16>,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub email_client: Arc<dyn EmailTransport>,
+    pub templates: Templates,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub base_url: String,
+    pub subscription_tokens: SubscriptionTokenSettings,
 }
 
 impl TestApp {
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_resend_confirmation(
+                &self.db_pool,
+                &self.templates,
+                &self.base_url,
+                &self.subscription_tokens,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_dispatch_email(&self.db_pool, self.email_client.as_ref())
+                .await
+                .unwrap()
+            {
+                break;
+            }
+        }
+        while let ExecutionOutcome::TaskCompleted = self.execute_delivery_tasks().await {}
+    }
+
+    /// Deliver a single chunk of newsletter emails, as one worker iteration would.
+    pub async fn execute_delivery_tasks(&self) -> ExecutionOutcome {
+        try_execute_task(
+            &self.db_pool,
+            self.email_client.as_ref(),
+            &self.templates,
+            &self.base_url,
+        )
+        .await
+        .unwrap()
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
-        reqwest::Client::new()
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_with_accept_language(
+        &self,
+        body: String,
+        accept_language: &str,
+    ) -> reqwest::Response {
+        self.api_client
             .post(format!("{}/subscriptions", self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept-Language", accept_language)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_with_idempotency_key(
+        &self,
+        body: String,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Idempotency-Key", idempotency_key)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: &serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletter_preview(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters/preview", self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/resend-confirmation", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/unsubscribe?token={token}", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Mimic an RFC 8058 one-click unsubscribe request sent by a mail client.
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe?token={token}", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Issue an unsubscribe token to the only subscriber, as a newsletter email would.
+    pub async fn issue_unsubscribe_token(&self) -> String {
+        let token = SubscriptionToken::new();
+        sqlx::query!(
+            "INSERT INTO unsubscribe_tokens (unsubscribe_token_hash, subscriber_id) SELECT $1, id FROM subscriptions",
+            token.hash(),
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to store the unsubscribe token.");
+        token.as_ref().to_owned()
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn login_as_test_user(&self) {
+        self.post_login(&serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password,
+        }))
+        .await;
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         // Extract the link from one of the request fields.
@@ -65,6 +314,11 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application with test-specific tweaks to its configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     LazyLock::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -73,9 +327,14 @@ pub async fn spawn_app() -> TestApp {
         let mut c = get_configuration().expect("Failed to read configuration.");
         // Use a different database for each test case
         c.database.database_name = Uuid::new_v4().to_string();
-        // Use a random OS port
+        // Use random OS ports
         c.application.port = 0;
+        c.application.metrics_port = Some(0);
+        c.email_client.kind = EmailTransportKind::Http;
         c.email_client.base_url = email_server.uri();
+        // Tests assert on the exact number of requests hitting the mock server
+        c.email_client.retry.max_attempts = 1;
+        configure(&mut c);
         c
     };
 
@@ -87,15 +346,64 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
     let port = application.port();
     let address = format!("http://127.0.0.1:{port}");
+    let metrics_port = application.metrics_port();
 
     tokio::spawn(application.run_until_stopped());
 
-    TestApp {
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    let test_app = TestApp {
         address,
         port,
+        metrics_port,
         db_pool: Application::get_connection_pool(&configuration.database),
         email_server,
-    }
+        email_client: configuration.email_client.client(),
+        templates: Templates::load(&configuration.templates.directory).expect("Failed to load templates"),
+        test_user: TestUser::generate(),
+        api_client,
+        base_url: configuration.application.base_url.clone(),
+        subscription_tokens: configuration.subscription_tokens.clone(),
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
+}
+
+/// Use the public API of the application under test to create
+/// an unconfirmed subscriber.
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
 }
 
 pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
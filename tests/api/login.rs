@@ -0,0 +1,135 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use secrecy::SecretString;
+use zero2prod::authentication::{Credentials, create_initial_admin};
+use zero2prod::configuration::InitialAdminSettings;
+
+#[tokio::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Try to login
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed.</i></p>"));
+
+    // Act - Part 3 - Reload the login page
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed."));
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Login
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_session_id_is_rotated_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    // Get an anonymous session first
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    }))
+    .await;
+    let anonymous_session = sqlx::query!("SELECT id FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the anonymous session.");
+
+    // Act
+    app.login_as_test_user().await;
+
+    // Assert
+    let sessions = sqlx::query!("SELECT id FROM sessions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch sessions.");
+    assert_eq!(sessions.len(), 1);
+    assert_ne!(sessions[0].id, anonymous_session.id);
+}
+
+#[tokio::test]
+async fn the_initial_admin_comes_from_the_configuration() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.initial_admin = Some(InitialAdminSettings {
+            username: "first-admin".into(),
+            password: SecretString::from("a-deployment-secret"),
+        })
+    })
+    .await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "first-admin",
+            "password": "a-deployment-secret"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_initial_admin_is_not_created_once_there_are_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let credentials = Credentials {
+        username: "another-admin".into(),
+        password: SecretString::from("a-deployment-secret"),
+    };
+
+    // Act
+    let created = create_initial_admin(credentials, &app.db_pool).await.unwrap();
+
+    // Assert
+    assert!(!created);
+    let n_users = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_users, 1);
+}
+
+#[tokio::test]
+async fn there_is_no_admin_with_a_well_known_password() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "admin",
+            "password": "everythinghastostartsomewhere"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
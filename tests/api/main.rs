@@ -1,4 +1,14 @@
+mod admin_dashboard;
 mod confirm_subscriptions;
+mod email_outbox;
 mod health_check;
 mod helpers;
+mod login;
+mod metrics;
+mod newsletters;
+mod openapi;
+mod problem_details;
+mod resend_confirmation;
+mod subscription_sweeper;
 mod subscriptions;
+mod unsubscribe;
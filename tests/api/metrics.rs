@@ -0,0 +1,106 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app, spawn_app_with};
+
+async fn get_metrics(app: &TestApp, port: u16) -> reqwest::Response {
+    app.api_client
+        .get(format!("http://127.0.0.1:{port}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// The value of a series. Every test shares the process-wide recorder, so
+/// values may include what other tests recorded in the meantime.
+fn value_of(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("{series} is missing from:\n{metrics}"))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_and_status() {
+    // Arrange
+    let app = spawn_app().await;
+    app.api_client
+        .get(format!("{}/health-check", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = get_metrics(&app, app.metrics_port.unwrap()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let metrics = response.text().await.unwrap();
+    assert!(
+        value_of(
+            &metrics,
+            r#"http_requests_total{method="GET",path="/health-check",status="200"}"#
+        ) >= 1.0
+    );
+    assert!(
+        value_of(
+            &metrics,
+            r#"http_request_duration_seconds_count{method="GET",path="/health-check",status="200"}"#
+        ) >= 1.0
+    );
+    value_of(&metrics, r#"db_pool_connections{state="idle"}"#);
+    value_of(&metrics, "db_pool_max_connections");
+}
+
+#[tokio::test]
+async fn the_subscription_funnel_and_email_sends_are_counted() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_client.provider_name = Some("primary".into())).await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+    app.post_unsubscribe(&token).await.error_for_status().unwrap();
+
+    // Act
+    let metrics = get_metrics(&app, app.metrics_port.unwrap()).await.text().await.unwrap();
+
+    // Assert
+    assert!(value_of(&metrics, "subscriptions_created_total") >= 1.0);
+    assert!(value_of(&metrics, "subscriptions_confirmed_total") >= 1.0);
+    assert!(value_of(&metrics, "subscriptions_unsubscribed_total") >= 1.0);
+    assert!(value_of(&metrics, r#"email_sends_total{provider="primary",outcome="delivered"}"#) >= 1.0);
+    assert!(
+        value_of(
+            &metrics,
+            r#"email_send_duration_seconds_count{provider="primary",operation="single"}"#
+        ) >= 1.0
+    );
+    assert!(value_of(&metrics, "email_rate_limit_wait_seconds_count") >= 1.0);
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_on_the_public_port() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let public_response = get_metrics(&app, app.port).await;
+    let admin_response = get_metrics(&app, app.metrics_port.unwrap()).await;
+
+    // Assert
+    assert_eq!(404, public_response.status().as_u16());
+    assert_eq!(200, admin_response.status().as_u16());
+    assert!(admin_response.text().await.unwrap().contains("db_pool_connections"));
+}
+
+#[tokio::test]
+async fn metrics_are_served_on_the_public_port_without_a_metrics_port() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.metrics_port = None).await;
+
+    // Act
+    let response = get_metrics(&app, app.port).await;
+
+    // Assert
+    assert_eq!(None, app.metrics_port);
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("db_pool_connections"));
+}
@@ -0,0 +1,634 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{any, body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::ExecutionOutcome;
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+fn markdown_newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "markdown": "# Hello\n\nRead **this** [online](https://example.com/issue).\n\n<script>alert(1)</script>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn newsletters_skip_confirmed_subscribers_with_an_invalid_stored_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+        VALUES (gen_random_uuid(), 'definitely-not-an-email', 'broken', now(), 'confirmed')",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn newsletters_returns_422_for_invalid_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (serde_json::json!({"title": "Newsletter!"}), "missing content"),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        // Act
+        let response = app.post_newsletters(invalid_body).await;
+
+        // Assert
+        assert_eq!(
+            422,
+            response.status().as_u16(),
+            "The API did not fail with 422 Unprocessable Entity when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_kept_in_the_queue_for_a_later_retry() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!("SELECT n_retries, execute_after FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery task.");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.execute_after > chrono::Utc::now());
+}
+
+#[tokio::test]
+async fn failed_deliveries_do_not_leave_unsubscribe_tokens_behind() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM unsubscribe_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(r#"Basic realm="publish""#, response.headers()["WWW-Authenticate"]);
+}
+
+#[tokio::test]
+async fn non_existing_user_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(r#"Basic realm="publish""#, response.headers()["WWW-Authenticate"]);
+}
+
+#[tokio::test]
+async fn invalid_password_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = &app.test_user.username;
+    let password = Uuid::new_v4().to_string();
+    assert_ne!(app.test_user.password, password);
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(r#"Basic realm="publish""#, response.headers()["WWW-Authenticate"]);
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Submit newsletter
+    let response = app
+        .post_newsletters_with_idempotency_key(&newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Act - Part 2 - Submit newsletter again
+    let response = app
+        .post_newsletters_with_idempotency_key(&newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Assert - the issue was only enqueued once
+    app.dispatch_all_pending_emails().await;
+    let n_issues = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_submissions_are_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let body = newsletter_request_body();
+
+    // Act - Submit two newsletter forms concurrently
+    let response1 = app.post_newsletters_with_idempotency_key(&body, &idempotency_key);
+    let response2 = app.post_newsletters_with_idempotency_key(&body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    // Assert
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(response1.text().await.unwrap(), response2.text().await.unwrap());
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_another_issue_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut other_body = newsletter_request_body();
+    other_body["title"] = "Another newsletter title".into();
+
+    // Act
+    let response1 = app
+        .post_newsletters_with_idempotency_key(&newsletter_request_body(), &idempotency_key)
+        .await;
+    let response2 = app
+        .post_newsletters_with_idempotency_key(&other_body, &idempotency_key)
+        .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 422);
+    let problem: serde_json::Value = response2.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "The idempotency key was already used for a different request."
+    );
+}
+
+#[tokio::test]
+async fn an_expired_idempotency_key_can_be_used_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut other_body = newsletter_request_body();
+    other_body["title"] = "Another newsletter title".into();
+    app.post_newsletters_with_idempotency_key(&newsletter_request_body(), &idempotency_key)
+        .await;
+    // The sweeper has not run yet
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '25 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_newsletters_with_idempotency_key(&other_body, &idempotency_key)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 2);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn an_invalid_idempotency_key_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletters_with_idempotency_key(&newsletter_request_body(), &"a".repeat(100))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn newsletter_emails_carry_one_click_unsubscribe_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "Headers": {
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+            }
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let header = body["Headers"]["List-Unsubscribe"].as_str().unwrap();
+    let prefix = format!("<{}/subscriptions/unsubscribe?token=", app.base_url);
+    let token = header.strip_prefix(&prefix).unwrap().strip_suffix('>').unwrap();
+    assert_eq!(app.post_unsubscribe(token).await.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_subscribers_who_left_after_publishing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_in_batches() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..3 {
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+            VALUES ($1, $2, $3, now(), 'confirmed')",
+            Uuid::new_v4(),
+            format!("subscriber{i}@example.com"),
+            format!("Subscriber {i}"),
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    Mock::given(path("/emails/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let messages = body.as_array().unwrap();
+    assert_eq!(messages.len(), 3);
+    // Every message carries its own recipient's unsubscribe link
+    let links: std::collections::HashSet<_> = messages
+        .iter()
+        .map(|m| m["Headers"]["List-Unsubscribe"].as_str().unwrap())
+        .collect();
+    assert_eq!(links.len(), 3);
+    let n_tasks_left = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks_left, 0);
+}
+
+#[tokio::test]
+async fn deliveries_are_recorded_chunk_by_chunk() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..11 {
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+            VALUES ($1, $2, $3, now(), 'confirmed')",
+            Uuid::new_v4(),
+            format!("subscriber{i}@example.com"),
+            format!("Subscriber {i}"),
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // The first chunk goes through, the provider goes down before the second one.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let tasks_left = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tasks_left.len(), 1);
+    assert_eq!(tasks_left[0].n_retries, 1);
+}
+
+#[tokio::test]
+async fn deliveries_being_sent_are_not_claimed_by_another_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..11 {
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+            VALUES ($1, $2, $3, now(), 'confirmed')",
+            Uuid::new_v4(),
+            format!("subscriber{i}@example.com"),
+            format!("Subscriber {i}"),
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Slow enough for a second worker to come by while the first chunk is on its way.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500)))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+
+    // Act
+    let (_, second_worker) = tokio::join!(app.execute_delivery_tasks(), async {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        app.execute_delivery_tasks().await
+    });
+    let third_worker = app.execute_delivery_tasks().await;
+
+    // Assert
+    assert!(matches!(second_worker, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(third_worker, ExecutionOutcome::EmptyQueue));
+    let mut recipients = Vec::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        match body.as_array() {
+            Some(messages) => recipients.extend(messages.iter().map(|m| m["To"].clone())),
+            None => recipients.push(body["To"].clone()),
+        }
+    }
+    let unique_recipients: std::collections::HashSet<_> = recipients.iter().map(|r| r.to_string()).collect();
+    assert_eq!(recipients.len(), 11);
+    assert_eq!(unique_recipients.len(), 11);
+}
+
+#[tokio::test]
+async fn newsletters_written_in_markdown_are_delivered_as_html_and_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletters(markdown_newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["Html"].as_str().unwrap();
+    let text = body["Text"].as_str().unwrap();
+    assert!(html.contains("<strong>this</strong>"));
+    assert!(!html.contains("<script>"));
+    assert!(text.contains("Read this online (https://example.com/issue)."));
+    assert!(!text.contains("**"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_preview_a_newsletter() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_newsletter_preview(&markdown_newsletter_request_body()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn newsletter_previews_are_rendered_without_being_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as_test_user().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletter_preview(&markdown_newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let preview: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(preview["subject"], "Newsletter title");
+    assert!(preview["html"].as_str().unwrap().contains("<strong>this</strong>"));
+    assert!(preview["text"].as_str().unwrap().contains("Read this online"));
+    assert!(preview["text"].as_str().unwrap().contains("Unsubscribe: "));
+}
@@ -0,0 +1,58 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn the_api_document_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert_eq!("3.1.0", document["openapi"]);
+    for path in ["/subscriptions", "/subscriptions/confirm", "/health-check"] {
+        assert!(document["paths"].get(path).is_some(), "{path} is not documented");
+    }
+}
+
+#[tokio::test]
+async fn the_api_reference_is_served_when_enabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.api_docs_ui = true).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/docs", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("/subscriptions/confirm"));
+}
+
+#[tokio::test]
+async fn the_api_reference_is_not_served_when_disabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.api_docs_ui = false).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/docs", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,132 @@
+use crate::helpers::spawn_app;
+use uuid::Uuid;
+
+async fn problem(response: reqwest::Response) -> serde_json::Value {
+    assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+    response.json().await.expect("The problem is not valid JSON.")
+}
+
+#[tokio::test]
+async fn validation_errors_are_reported_as_problems() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=Ursula&email=definitely-not-an-email";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let problem = problem(response).await;
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["detail"], "The email address is not valid.");
+    assert_eq!(problem["instance"], "/subscriptions");
+    assert!(Uuid::parse_str(problem["request_id"].as_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn every_request_gets_its_own_id() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=&email=ursula_le_guin%40gmail.com";
+
+    // Act
+    let first = problem(app.post_subscriptions(body.into()).await).await;
+    let second = problem(app.post_subscriptions(body.into()).await).await;
+
+    // Assert
+    assert_ne!(first["request_id"], second["request_id"]);
+}
+
+#[tokio::test]
+async fn unexpected_errors_do_not_disclose_their_cause() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let problem = problem(response).await;
+    assert_eq!(problem["title"], "Internal Server Error");
+    let detail = problem["detail"].as_str().unwrap();
+    assert!(!detail.contains("email"), "{detail}");
+    assert!(!detail.contains("column"), "{detail}");
+}
+
+#[tokio::test]
+async fn rejected_request_bodies_are_reported_as_problems() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_subscriptions("name=le%20guin".into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let problem = problem(response).await;
+    assert_eq!(problem["status"], 422);
+    assert!(problem["detail"].as_str().unwrap().contains("email"));
+}
+
+#[tokio::test]
+async fn unknown_routes_are_reported_as_problems() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/does-not-exist", app.address)).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let problem = problem(response).await;
+    assert_eq!(problem["title"], "Not Found");
+    assert_eq!(problem["instance"], "/does-not-exist");
+}
+
+#[tokio::test]
+async fn unsupported_methods_are_reported_as_problems_and_keep_their_headers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .delete(format!("{}/subscriptions", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 405);
+    assert_eq!(response.headers()["Allow"], "POST");
+    problem(response).await;
+}
+
+#[tokio::test]
+async fn failed_authentication_is_reported_as_a_problem_without_saying_why() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", app.address))
+        .basic_auth(&app.test_user.username, Some("not the password"))
+        .json(&serde_json::json!({"title": "Title", "content": {"markdown": "Body"}}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], r#"Basic realm="publish""#);
+    let problem = problem(response).await;
+    assert_eq!(problem["detail"], "Invalid credentials.");
+}
@@ -0,0 +1,141 @@
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const BODY: &str = "email=ursula_le_guin%40gmail.com";
+
+#[tokio::test]
+async fn resending_a_confirmation_invalidates_the_previous_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let old_links = create_unconfirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_resend_confirmation(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let new_links = app.get_confirmation_links(&email_request);
+    assert_ne!(old_links.html, new_links.html);
+    assert_eq!(reqwest::get(old_links.html).await.unwrap().status().as_u16(), 401);
+    assert_eq!(reqwest::get(new_links.html).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_a_confirmed_subscriber_sends_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_resend_confirmation(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_an_unknown_address_looks_like_a_success() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_resend_confirmation(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resends_are_rate_limited_per_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // The default configuration allows 3 resends per hour
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..5 {
+        // Act
+        let response = app.post_resend_confirmation(BODY.into()).await;
+        app.dispatch_all_pending_emails().await;
+        app.dispatch_all_pending_emails().await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn resending_to_an_invalid_address_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_resend_confirmation("email=definitely-not-an-email".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn resend_requests_are_answered_before_touching_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let tokens_before = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.post_resend_confirmation(BODY.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let tokens_after = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens_before.len(), tokens_after.len());
+    assert_eq!(
+        tokens_before[0].subscription_token_hash,
+        tokens_after[0].subscription_token_hash
+    );
+    let n_emails = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_emails, 1);
+}
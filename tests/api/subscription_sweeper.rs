@@ -0,0 +1,207 @@
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+use zero2prod::subscription_sweeper::sweep_expired_subscriptions;
+
+#[tokio::test]
+async fn the_sweeper_deletes_expired_pending_subscriptions() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let n_subscriptions = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscriptions, 0);
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn the_sweeper_keeps_pending_subscriptions_with_a_valid_token() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The pending subscription was deleted.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn the_sweeper_keeps_confirmed_subscriptions() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The confirmed subscription was deleted.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmation_links_keep_working_for_confirmed_subscribers_after_a_sweep() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_sweeper_purges_emails_sent_more_than_a_week_ago() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE email_outbox SET delivered_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let n_emails = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_emails, 0);
+}
+
+#[tokio::test]
+async fn the_sweeper_keeps_recently_sent_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let n_emails = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_emails, 1);
+}
+
+#[tokio::test]
+async fn the_sweeper_purges_idempotency_keys_older_than_a_day() {
+    // Arrange
+    let app = spawn_app().await;
+    for (key, age) in [("recent", "1 hour"), ("stale", "25 hours")] {
+        sqlx::query!(
+            r#"
+            INSERT INTO idempotency (user_id, idempotency_key, request_hash, created_at)
+            VALUES ($1, $2, ''::bytea, now() - $3::text::interval)
+            "#,
+            uuid::Uuid::nil(),
+            key,
+            age,
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let keys = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].idempotency_key, "recent");
+}
+
+#[tokio::test]
+async fn the_sweeper_purges_unsubscribe_tokens_no_longer_needed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.issue_unsubscribe_token().await;
+    sqlx::query!("UPDATE unsubscribe_tokens SET created_at = now() - interval '13 months'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.issue_unsubscribe_token().await;
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM unsubscribe_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn the_sweeper_purges_the_unsubscribe_tokens_of_subscribers_who_left() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+    app.issue_unsubscribe_token().await;
+    app.post_unsubscribe(&token).await;
+
+    // Act
+    sweep_expired_subscriptions(&app.db_pool).await.unwrap();
+
+    // Assert
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM unsubscribe_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+}
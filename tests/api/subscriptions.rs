@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
 use reqwest::StatusCode;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
@@ -18,6 +18,7 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
 
     // Act
     let response = app.post_subscriptions(body.to_string()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Assert
     assert_eq!(200, response.status().as_u16());
@@ -38,6 +39,7 @@ async fn subscribe_persists_the_new_subscriber() {
 
     // Act
     app.post_subscriptions(body.to_string()).await;
+    app.dispatch_all_pending_emails().await;
 
     let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
         .fetch_one(&app.db_pool)
@@ -63,6 +65,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -79,6 +82,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Assert
     // Get the first intercepted request
@@ -88,6 +92,105 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_addressed_to_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=Tom%20%26%20Jerry&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome!");
+    assert!(body["Html"].as_str().unwrap().contains("Hi Tom &amp; Jerry,"));
+    assert!(body["Text"].as_str().unwrap().contains("Hi Tom & Jerry,"));
+}
+
+#[tokio::test]
+async fn subscribe_sends_the_confirmation_email_in_the_language_of_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions_with_accept_language(body.into(), "fr-FR, es-MX;q=0.9, en;q=0.8")
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "¡Te damos la bienvenida!");
+    assert!(body["Text"].as_str().unwrap().contains("Hola, le guin:"));
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "es");
+}
+
+#[tokio::test]
+async fn a_language_picked_on_the_form_overrides_the_browser_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=en";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions_with_accept_language(body.into(), "es").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome!");
+}
+
+#[tokio::test]
+async fn unsupported_languages_fall_back_to_english() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions_with_accept_language(body.into(), "de-DE, fr;q=0.5")
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome!");
+}
+
 #[tokio::test]
 async fn subscribe_returns_a_422_when_data_is_missing() {
     // Arrange
@@ -153,3 +256,467 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn retrying_a_subscription_with_the_same_idempotency_key_replays_the_response() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response1 = app
+        .post_subscriptions_with_idempotency_key(body.into(), &idempotency_key)
+        .await;
+    let response2 = app
+        .post_subscriptions_with_idempotency_key(body.into(), &idempotency_key)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_another_subscription_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response1 = app
+        .post_subscriptions_with_idempotency_key(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com".into(),
+            &idempotency_key,
+        )
+        .await;
+    let response2 = app
+        .post_subscriptions_with_idempotency_key(
+            "name=Octavia%20Butler&email=octavia_butler%40gmail.com".into(),
+            &idempotency_key,
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 422);
+    let n_subscriptions = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscriptions, 1);
+}
+
+#[tokio::test]
+async fn concurrent_subscriptions_with_the_same_idempotency_key_are_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(200)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response1 = app.post_subscriptions_with_idempotency_key(body.into(), &idempotency_key);
+    let response2 = app.post_subscriptions_with_idempotency_key(body.into(), &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_a_fresh_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response1 = app.post_subscriptions(body.into()).await;
+    let response2 = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+
+    // Only the latest link is valid
+    assert_eq!(reqwest::get(first_link).await.unwrap().status().as_u16(), 401);
+    assert_eq!(reqwest::get(second_link).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_is_rate_limited() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    // The first confirmation email, then the 3 resends the default configuration allows per hour
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..6 {
+        // Act
+        let response = app.post_subscriptions(body.into()).await;
+        app.dispatch_all_pending_emails().await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_keeps_the_name_and_language() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Act
+    app.post_subscriptions("name=someone%20else&email=ursula_le_guin%40gmail.com&locale=es".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT name, locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.locale, "en");
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["Text"].as_str().unwrap().contains("le guin"));
+    assert!(!body["Text"].as_str().unwrap().contains("someone else"));
+}
+
+#[tokio::test]
+async fn subscribing_again_after_confirming_succeeds_without_sending_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_restarts_double_opt_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_reports_a_pending_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions_json(&body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(body, serde_json::json!({"status": "pending_confirmation"}));
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn json_answers_do_not_tell_confirmed_subscribers_from_new_ones() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let confirmed = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+    let new = serde_json::json!({
+        "name": "Octavia Butler",
+        "email": "octavia_butler@gmail.com"
+    });
+
+    // Act
+    let confirmed_response = app.post_subscriptions_json(&confirmed).await;
+    let new_response = app.post_subscriptions_json(&new).await;
+
+    // Assert
+    assert_eq!(confirmed_response.status(), new_response.status());
+    assert_eq!(
+        confirmed_response.text().await.unwrap(),
+        new_response.text().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn form_submissions_get_json_when_they_ask_for_it() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn form_submissions_get_an_empty_body_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/emails"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_reports_which_json_field_is_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "definitely-not-an-email"}),
+            "email",
+        ),
+    ];
+
+    for (body, field) in test_cases {
+        // Act
+        let response = app.post_subscriptions_json(&body).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "The payload was {body}.");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["errors"][0]["field"], field);
+        assert!(body["errors"][0]["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_422_when_json_fields_are_missing() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({"name": "le guin"});
+
+    // Act
+    let response = app.post_subscriptions_json(&body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({"name": "Ursula (the author)", "email": "definitely-not-an-email"});
+
+    // Act
+    let response = app.post_subscriptions_json(&body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["errors"],
+        serde_json::json!([
+            {
+                "field": "name",
+                "code": "forbidden_characters",
+                "message": "The name must not contain any of / ( ) \" < > \\ { }."
+            },
+            {
+                "field": "email",
+                "code": "invalid",
+                "message": "The email address is not valid."
+            }
+        ])
+    );
+    assert_eq!(
+        body["detail"],
+        "The name must not contain any of / ( ) \" < > \\ { }. The email address is not valid."
+    );
+}
+
+#[tokio::test]
+async fn invalid_form_submissions_report_the_reason_of_each_field() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("name=&email=ursula_le_guin%40gmail.com", vec![("name", "empty")]),
+        ("name=Ursula&email=", vec![("email", "empty")]),
+        (
+            "name=%20&email=not-an-email",
+            vec![("name", "empty"), ("email", "invalid")],
+        ),
+    ];
+
+    for (body, expected) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body.to_string()).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "The payload was {body}.");
+        let problem: serde_json::Value = response.json().await.unwrap();
+        let errors: Vec<_> = problem["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| (e["field"].as_str().unwrap(), e["code"].as_str().unwrap()))
+            .collect();
+        assert_eq!(errors, expected, "The payload was {body}.");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+
+#[tokio::test]
+async fn the_unsubscribe_page_asks_for_confirmation_without_unsubscribing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+
+    // Act
+    let response = app.get_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<form"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+
+    // Act
+    let response = app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribing_invalidates_pending_confirmation_links() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+
+    // Act
+    app.post_unsubscribe(&token).await;
+
+    // Assert
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribing_twice_is_harmless() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+    app.post_unsubscribe(&token).await;
+
+    // Act
+    let response = app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn malformed_unsubscribe_tokens_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let get_response = app.get_unsubscribe("abc").await;
+    let post_response = app.post_unsubscribe("abc").await;
+
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 400);
+    assert_eq!(post_response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unknown_unsubscribe_tokens_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = "5J91vXYKj2xP8LmN3qRt4wZhA";
+
+    // Act
+    let get_response = app.get_unsubscribe(token).await;
+    let post_response = app.post_unsubscribe(token).await;
+
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_pages_are_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET locale = 'es'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let token = app.issue_unsubscribe_token().await;
+
+    // Act
+    let form = app.get_unsubscribe(&token).await.text().await.unwrap();
+    let confirmation = app.post_unsubscribe(&token).await.text().await.unwrap();
+
+    // Assert
+    assert!(form.contains(r#"<html lang="es">"#), "{form}");
+    assert!(confirmation.contains(r#"<html lang="es">"#), "{confirmation}");
+}
+
+#[tokio::test]
+async fn only_the_hash_of_unsubscribe_tokens_is_stored() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.issue_unsubscribe_token().await;
+
+    // Act
+    let stored = sqlx::query!("SELECT unsubscribe_token_hash FROM unsubscribe_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_ne!(stored.unsubscribe_token_hash, token);
+    assert_eq!(stored.unsubscribe_token_hash.len(), 64);
+}